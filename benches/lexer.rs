@@ -1,4 +1,7 @@
-use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use la_texer::{Token, SYMBOLS};
+
+mod baseline;
 
 /// Benchmarks the performance of tokenizing a complex LaTeX expression.
 fn bench_tokenize_complex(c: &mut Criterion) {
@@ -30,5 +33,90 @@ fn bench_tokenize_simple(c: &mut Criterion) {
     });
 }
 
-criterion_group!(benches, bench_tokenize_complex, bench_tokenize_simple,);
+/// A megabyte of input made almost only of commands, cycling through every known command
+fn command_dense_corpus() -> String {
+    let commands: Vec<_> = SYMBOLS
+        .iter()
+        .filter(|symbol| symbol.command.chars().all(|c| c.is_ascii_alphabetic()))
+        .map(|symbol| symbol.command)
+        .collect();
+    let mut corpus = String::with_capacity(1 << 20);
+    for command in commands.iter().cycle() {
+        if corpus.len() >= 1 << 20 {
+            break;
+        }
+        corpus.push('\\');
+        corpus.push_str(command);
+        corpus.push(' ');
+    }
+    corpus
+}
+
+/// Benchmarks the performance of tokenizing a megabyte of commands.
+fn bench_tokenize_command_dense(c: &mut Criterion) {
+    let input = command_dense_corpus();
+
+    let mut group = c.benchmark_group("tokenize_command_dense");
+    group.throughput(Throughput::Bytes(input.len() as u64));
+    group.sample_size(20);
+    group.bench_function("1MiB", |b| {
+        b.iter(|| {
+            let lexer = la_texer::Lexer::new(black_box(&input));
+            for token in lexer {
+                black_box(token);
+            }
+        })
+    });
+    group.finish();
+}
+
+/// Compares the command lookup of the lexer with the `match` it replaced.
+fn bench_command_lookup(c: &mut Criterion) {
+    let commands = [
+        "alpha",
+        "sum",
+        "rightarrow",
+        "Longleftrightarrow",
+        "notacommand",
+    ];
+
+    let mut group = c.benchmark_group("command_lookup");
+    for command in commands {
+        group.bench_with_input(
+            BenchmarkId::new("generated", command),
+            command,
+            |b, command| b.iter(|| Token::from_command(black_box(command))),
+        );
+        group.bench_with_input(
+            BenchmarkId::new("baseline", command),
+            command,
+            |b, command| b.iter(|| baseline::from_command(black_box(command))),
+        );
+    }
+
+    let every: Vec<_> = SYMBOLS.iter().map(|symbol| symbol.command).collect();
+    group.bench_function(BenchmarkId::new("generated", "every"), |b| {
+        b.iter(|| {
+            for command in &every {
+                black_box(Token::from_command(black_box(command)));
+            }
+        })
+    });
+    group.bench_function(BenchmarkId::new("baseline", "every"), |b| {
+        b.iter(|| {
+            for command in &every {
+                black_box(baseline::from_command(black_box(command)));
+            }
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    bench_tokenize_complex,
+    bench_tokenize_simple,
+    bench_tokenize_command_dense,
+    bench_command_lookup,
+);
 criterion_main!(benches);
@@ -1,4 +1,4 @@
-use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion};
 use la_texer::Parser;
 
 /// Benchmarks the performance of parsing a complex LaTeX expression.
@@ -25,5 +25,34 @@ fn bench_parse_simple(c: &mut Criterion) {
     });
 }
 
-criterion_group!(benches, bench_parse_complex, bench_parse_simple,);
+/// Compares parsing into boxed nodes with parsing into an arena, and dropping either of them.
+fn bench_parse_arena(c: &mut Criterion) {
+    let input = r#"\frac{\alpha x_{i}^{2}}{\sqrt[3]{y + \beta}} - "#.repeat(2000);
+
+    let mut group = c.benchmark_group("parse_arena");
+    group.bench_function("boxed", |b| {
+        b.iter(|| black_box(Parser::new(black_box(&input)).parse()))
+    });
+    group.bench_function("arena", |b| {
+        b.iter(|| black_box(Parser::new(black_box(&input)).parse_ast()))
+    });
+    group.bench_function("drop_boxed", |b| {
+        b.iter_batched(|| Parser::new(&input).parse(), drop, BatchSize::LargeInput)
+    });
+    group.bench_function("drop_arena", |b| {
+        b.iter_batched(
+            || Parser::new(&input).parse_ast(),
+            drop,
+            BatchSize::LargeInput,
+        )
+    });
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    bench_parse_complex,
+    bench_parse_simple,
+    bench_parse_arena,
+);
 criterion_main!(benches);
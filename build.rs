@@ -0,0 +1,185 @@
+//! Generates the symbol table from `data/symbols.tsv`, along with a lookup of the
+//! index of every command in it and a perfect hash map from glyphs to their atom class
+//!
+//! The code points of the table are checked against the rows of unicode-math's table vendored
+//! in `data/unicode-math-table.tex`.
+
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    env,
+    fmt::Write as _,
+    fs,
+    path::Path,
+};
+
+const SYMBOLS: &str = "data/symbols.tsv";
+const UNICODE_MATH: &str = "data/unicode-math-table.tex";
+
+/// Commands with their index in the table, by length and then first byte
+type Commands<'a> = BTreeMap<usize, BTreeMap<u8, Vec<(&'a str, usize)>>>;
+
+fn main() {
+    println!("cargo:rerun-if-changed={SYMBOLS}");
+    println!("cargo:rerun-if-changed={UNICODE_MATH}");
+    let data = fs::read_to_string(SYMBOLS).expect("failed to read the symbol data");
+    let table = fs::read_to_string(UNICODE_MATH).expect("failed to read the unicode-math table");
+    let mut unicode_math = unicode_math(&table);
+
+    let mut symbols = String::from("[\n");
+    let mut seen = HashSet::new();
+    let mut commands = Commands::new();
+    let mut glyphs = HashSet::new();
+    let mut classes = phf_codegen::Map::new();
+    let rows = data
+        .lines()
+        .filter(|line| !line.is_empty() && !line.starts_with('#'));
+    for (index, row) in rows.enumerate() {
+        let columns: Vec<_> = row.split('\t').collect();
+        let [command, token, class, glyph, package, name] = columns[..] else {
+            panic!("expected 6 columns in {row:?}");
+        };
+        let class = match class {
+            "-" => default_class(token),
+            class => Some(class),
+        };
+        let token = match glyph {
+            "-" => token.to_string(),
+            _ => {
+                let glyph = self::glyph(glyph);
+                check(&mut unicode_math, command, token, &glyph);
+                // The first command of a glyph gives its class
+                if let Some(class) = class.filter(|_| glyphs.insert(glyph.clone())) {
+                    classes.entry(glyph.clone(), &format!("AtomClass::{class}"));
+                }
+                token.replacen("(_", &format!("({glyph:?}"), 1)
+            }
+        };
+        let package = match package {
+            "-" => "None".to_string(),
+            package => format!("Some({package:?})"),
+        };
+        writeln!(
+            symbols,
+            "    Symbol::new({command:?}, Token::{token}, {package}, {name:?}),"
+        )
+        .unwrap();
+        // The first of duplicated commands is the one the lexer produces
+        if seen.insert(command) {
+            commands
+                .entry(command.len())
+                .or_default()
+                .entry(command.as_bytes()[0])
+                .or_default()
+                .push((command, index));
+        }
+    }
+    symbols.push(']');
+    let mut unused: Vec<_> = unicode_math.into_keys().collect();
+    unused.sort();
+    assert!(
+        unused.is_empty(),
+        "commands of {UNICODE_MATH} missing from {SYMBOLS}: {unused:?}"
+    );
+
+    let out = env::var("OUT_DIR").unwrap();
+    fs::write(Path::new(&out).join("symbols.rs"), symbols).unwrap();
+    fs::write(Path::new(&out).join("commands.rs"), lookup(&commands)).unwrap();
+    let classes = classes.build().to_string();
+    fs::write(Path::new(&out).join("classes.rs"), classes).unwrap();
+}
+
+/// Reads the code point of every command of unicode-math's table, from rows such as
+/// `\UnicodeMathSymbol{"02264}{\leq }{\mathrel}{less-than or equal to}%`
+fn unicode_math(table: &str) -> HashMap<&str, char> {
+    table
+        .lines()
+        .filter_map(|line| line.strip_prefix("\\UnicodeMathSymbol{\""))
+        .map(|row| {
+            let (code, rest) = row.split_once("}{\\").expect("expected a command");
+            let (command, _) = rest.split_once('}').expect("unterminated command");
+            let c = u32::from_str_radix(code, 16)
+                .ok()
+                .and_then(char::from_u32)
+                .unwrap_or_else(|| panic!("invalid code point in {row:?}"));
+            (command.trim_end(), c)
+        })
+        .collect()
+}
+
+/// Checks the glyph of a symbol against the row of unicode-math's table for its command, or for
+/// the `\mup` command of an upright Greek letter, removing that row from `unicode_math`
+fn check(unicode_math: &mut HashMap<&str, char>, command: &str, token: &str, glyph: &str) {
+    if ["Over(", "Under(", "Overbrace(", "Underbrace("]
+        .iter()
+        .any(|kind| token.starts_with(kind))
+    {
+        return;
+    }
+    let mut chars = glyph.chars();
+    let (Some(c), None) = (chars.next(), chars.next()) else {
+        return;
+    };
+    let found = unicode_math.remove(command).or_else(|| {
+        token.starts_with("Letter(").then_some(())?;
+        unicode_math.remove(format!("mup{command}").as_str())
+    });
+    if let Some(expected) = found {
+        assert_eq!(
+            c, expected,
+            "\\{command} is U+{:04X} in {SYMBOLS} but U+{:04X} in {UNICODE_MATH}",
+            c as u32, expected as u32
+        );
+    }
+}
+
+/// A `match` on the length and first byte of a command before its name, which spares
+/// comparing it against every command of the table
+fn lookup(commands: &Commands) -> String {
+    let mut code = String::from("match name.len() {\n");
+    for (len, firsts) in commands {
+        writeln!(code, "    {len} => match name.as_bytes()[0] {{").unwrap();
+        for (first, names) in firsts {
+            writeln!(code, "        {first} => match name {{").unwrap();
+            for (command, index) in names {
+                writeln!(code, "            {command:?} => Some({index}),").unwrap();
+            }
+            code.push_str("            _ => None,\n        },\n");
+        }
+        code.push_str("        _ => None,\n    },\n");
+    }
+    code.push_str("    _ => None,\n}");
+    code
+}
+
+/// The atom class of the glyph of a token that isn't an operator, such as `Open` for a
+/// `LSeperator`
+fn default_class(token: &str) -> Option<&'static str> {
+    let kind = token.split('(').next().unwrap();
+    match kind {
+        "Letter" | "Paren" => Some("Ord"),
+        "BigOp" | "Integral" | "Lim" | "Function" => Some("Op"),
+        "LSeperator" => Some("Open"),
+        "RSeperator" => Some("Close"),
+        _ => None,
+    }
+}
+
+/// Reads a glyph given as space separated code points such as `U+2264`, or as a quoted text
+fn glyph(column: &str) -> String {
+    if let Some(text) = column.strip_prefix('"') {
+        return text
+            .strip_suffix('"')
+            .expect("unterminated text")
+            .to_string();
+    }
+    column
+        .split(' ')
+        .map(|code| {
+            let hex = code.strip_prefix("U+").expect("code points start with U+");
+            u32::from_str_radix(hex, 16)
+                .ok()
+                .and_then(char::from_u32)
+                .unwrap_or_else(|| panic!("invalid code point {code}"))
+        })
+        .collect()
+}
@@ -1,193 +1,255 @@
-use la_texer::{DisplayStyle, Node, Parser};
-use std::io::Write;
+use la_texer::{
+    Accent, DisplayStyle, Grid, LineThickness, Node, Parser, Space, Token, Variant, Visit,
+};
+use std::fmt::Write;
 
 fn main() {
     let input = r#"\begin{center} If the exponent on $R$, $1-P$ is positive, then as $R$ approaches infinity the term will explode into infinity as well. If the exponent $1-P1$ is negative, then as $R$ approaches infinity it will be ina denominator, and the term will approach 0.  \begin{tabular}{c|c|c} $1-P > 0$ & $P<1$ & Diverges  \\ $1-P < 0$ & $P>1$ & Converges \\ \end{tabular} \\ Convergence will lead to \end{center}"#;
     let parser = Parser::new(input);
-    let mut output = Vec::new();
-    {
-        let mut bufwriter = std::io::BufWriter::new(&mut output);
-        write!(
-            bufwriter,
-            "<math xmlns=\"http://www.w3.org/1998/Math/MathML\">"
-        )
-        .unwrap();
-        let tokens = parser.parse();
-        println!("{tokens:#?}");
-        for token in tokens.into_iter() {
-            expand_node(&token, &mut bufwriter).unwrap();
-        }
-        write!(bufwriter, "</math>").unwrap();
+    let nodes = parser.parse();
+    println!("{nodes:#?}");
+    let mut mathml = MathMl::default();
+    mathml.push("<math xmlns=\"http://www.w3.org/1998/Math/MathML\">");
+    for node in &nodes {
+        mathml.visit_node(node);
     }
-    println!("{}", String::from_utf8(output).unwrap());
+    mathml.push("</math>");
+    println!("{}", mathml.out);
 }
 
-fn expand_node(node: &Node, buf: &mut dyn Write) -> std::io::Result<()> {
-    match node {
-        Node::Number(number) => write!(buf, "<mn>{number}</mn>"),
-        Node::Letter(letter, var) => match var {
-            la_texer::Variant::Italic => write!(buf, "<mi>{letter}</mi>"),
-            _ => write!(buf, "<mi mathvariant=\"{var}\">{letter}</mi>"),
-        },
-        Node::Operator(op) => {
-            if op.is_empty() {
-                write!(buf, "<mo mathvariant=\"italic\">∂</mo>")
-            } else {
-                write!(buf, "<mo>{op}</mo>")
-            }
-        }
-        Node::Function(fun, arg) => match arg {
-            Some(arg) => {
-                write!(buf, "<mi>{fun}</mi><mo>&#x2061;</mo>")?;
-                expand_node(arg, buf)
-            }
-            None => write!(buf, "<mi>{fun}</mi>"),
-        },
-        Node::Space(space) => write!(buf, "<mspace width=\"{space}em\"/>"),
-        Node::Subscript(a, b) => {
-            write!(buf, "<msub>")?;
-            expand_node(a, buf)?;
-            expand_node(b, buf)?;
-            write!(buf, "</msub>")
-        }
-        Node::Superscript(a, b) => {
-            write!(buf, "<msup>")?;
-            expand_node(a, buf)?;
-            expand_node(b, buf)?;
-            write!(buf, "</msup>")
-        }
-        Node::SubSup { target, sub, sup } => {
-            write!(buf, "<msubsup>")?;
-            expand_node(target, buf)?;
-            expand_node(sub, buf)?;
-            expand_node(sup, buf)?;
-            write!(buf, "</msubsup>")
-        }
-        Node::OverOp(op, acc, target) => {
-            write!(buf, "<mover>")?;
-            expand_node(target, buf)?;
-            write!(buf, "<mo accent=\"{acc}\">{op}</mo></mover>")
-        }
-        Node::UnderOp(op, acc, target) => {
-            write!(buf, "<munder>")?;
-            expand_node(target, buf)?;
-            write!(buf, "<mo accent=\"{acc}\">{op}</mo></munder>")
-        }
-        Node::Overset { over, target } => {
-            write!(buf, "<mover>")?;
-            expand_node(target, buf)?;
-            expand_node(over, buf)?;
-            write!(buf, "</mover>")
+/// Writes MathML, variants it does not override (such as `\usepackage`) write nothing
+#[derive(Default)]
+struct MathMl {
+    out: String,
+}
+
+impl MathMl {
+    fn push(&mut self, markup: &str) {
+        self.out.push_str(markup);
+    }
+
+    /// Wraps the children visited by `inner` in a `tag` element
+    fn element(&mut self, tag: &str, inner: impl FnOnce(&mut Self)) {
+        write!(self.out, "<{tag}>").unwrap();
+        inner(self);
+        write!(self.out, "</{tag}>").unwrap();
+    }
+}
+
+impl<'n, 'a: 'n> Visit<'n, &'a str> for MathMl {
+    fn visit_number(&mut self, number: &'n &'a str) {
+        write!(self.out, "<mn>{number}</mn>").unwrap();
+    }
+
+    fn visit_letter(&mut self, letter: &'n &'a str, variant: Variant) {
+        match variant {
+            Variant::Italic => write!(self.out, "<mi>{letter}</mi>"),
+            _ => write!(self.out, "<mi mathvariant=\"{variant}\">{letter}</mi>"),
         }
-        Node::Underset { under, target } => {
-            write!(buf, "<munder>")?;
-            expand_node(target, buf)?;
-            expand_node(under, buf)?;
-            write!(buf, "</munder>")
+        .unwrap();
+    }
+
+    fn visit_operator(&mut self, op: &'n &'a str) {
+        if op.is_empty() {
+            self.push("<mo mathvariant=\"italic\">∂</mo>");
+        } else {
+            write!(self.out, "<mo>{op}</mo>").unwrap();
         }
-        Node::UnderOver {
-            target,
-            under,
-            over,
-        } => {
-            write!(buf, "<munderover>")?;
-            expand_node(target, buf)?;
-            expand_node(under, buf)?;
-            expand_node(over, buf)?;
-            write!(buf, "</munderover>")
+    }
+
+    fn visit_function(&mut self, name: &'n &'a str, arg: Option<&'n Node<'a>>) {
+        write!(self.out, "<mi>{name}</mi>").unwrap();
+        if let Some(arg) = arg {
+            self.push("<mo>&#x2061;</mo>");
+            self.visit_node(arg);
         }
-        Node::Sqrt(degree, content) => match degree {
-            Some(degree) => {
-                write!(buf, "<mroot>")?;
-                expand_node(content, buf)?;
-                expand_node(degree, buf)?;
-                write!(buf, "</mroot>")
-            }
-            None => {
-                write!(buf, "<msqrt>")?;
-                expand_node(content, buf)?;
-                write!(buf, "</msqrt>")
-            }
-        },
-        Node::Frac(num, denom, lt) => {
-            write!(buf, "<mfrac linethickness=\"{lt}\">")?;
-            expand_node(num, buf)?;
-            expand_node(denom, buf)?;
-            write!(buf, "</mfrac>")
+    }
+
+    fn visit_space(&mut self, space: Space) {
+        write!(self.out, "<mspace width=\"{space}\"/>").unwrap();
+    }
+
+    fn visit_subscript(&mut self, target: &'n Node<'a>, sub: &'n Node<'a>) {
+        self.element("msub", |this| {
+            this.visit_node(target);
+            this.visit_node(sub);
+        });
+    }
+
+    fn visit_superscript(&mut self, target: &'n Node<'a>, sup: &'n Node<'a>) {
+        self.element("msup", |this| {
+            this.visit_node(target);
+            this.visit_node(sup);
+        });
+    }
+
+    fn visit_sub_sup(&mut self, target: &'n Node<'a>, sub: &'n Node<'a>, sup: &'n Node<'a>) {
+        self.element("msubsup", |this| {
+            this.visit_node(target);
+            this.visit_node(sub);
+            this.visit_node(sup);
+        });
+    }
+
+    fn visit_over_op(&mut self, op: &'n &'a str, accent: Accent, target: &'n Node<'a>) {
+        self.element("mover", |this| {
+            this.visit_node(target);
+            write!(this.out, "<mo accent=\"{accent}\">{op}</mo>").unwrap();
+        });
+    }
+
+    fn visit_under_op(&mut self, op: &'n &'a str, accent: Accent, target: &'n Node<'a>) {
+        self.element("munder", |this| {
+            this.visit_node(target);
+            write!(this.out, "<mo accent=\"{accent}\">{op}</mo>").unwrap();
+        });
+    }
+
+    fn visit_overset(&mut self, over: &'n Node<'a>, target: &'n Node<'a>) {
+        self.element("mover", |this| {
+            this.visit_node(target);
+            this.visit_node(over);
+        });
+    }
+
+    fn visit_underset(&mut self, under: &'n Node<'a>, target: &'n Node<'a>) {
+        self.element("munder", |this| {
+            this.visit_node(target);
+            this.visit_node(under);
+        });
+    }
+
+    fn visit_under_over(&mut self, target: &'n Node<'a>, under: &'n Node<'a>, over: &'n Node<'a>) {
+        self.element("munderover", |this| {
+            this.visit_node(target);
+            this.visit_node(under);
+            this.visit_node(over);
+        });
+    }
+
+    fn visit_sqrt(&mut self, degree: Option<&'n Node<'a>>, content: &'n Node<'a>) {
+        match degree {
+            Some(degree) => self.element("mroot", |this| {
+                this.visit_node(content);
+                this.visit_node(degree);
+            }),
+            None => self.element("msqrt", |this| this.visit_node(content)),
         }
-        Node::Row(nodes) => {
-            write!(buf, "<mrow>")?;
+    }
+
+    fn visit_frac(
+        &mut self,
+        numerator: &'n Node<'a>,
+        denominator: &'n Node<'a>,
+        thickness: LineThickness,
+    ) {
+        write!(self.out, "<mfrac linethickness=\"{thickness}\">").unwrap();
+        self.visit_node(numerator);
+        self.visit_node(denominator);
+        self.push("</mfrac>");
+    }
+
+    fn visit_row(&mut self, nodes: &'n [Node<'a>]) {
+        self.element("mrow", |this| {
             for node in nodes {
-                expand_node(node, buf)?;
+                this.visit_node(node);
             }
-            write!(buf, "</mrow>")
-        }
-        Node::Fenced {
-            open,
-            close,
-            content,
-        } => {
-            write!(
-                buf,
-                "<mrow>\
-                " // <mo stretchy=\"true\" form=\"prefix\">"
-            )?;
-            expand_node(open, buf)?;
-            // write!(buf, "</mo>")?;
-            expand_node(content, buf)?;
-            // write!(buf, "<mo stretchy=\"true\" form=\"postfix\">")?;
-            expand_node(close, buf)?;
-            write!(buf, "</mrow>")
-        }
-        Node::StrechedOp(stretchy, op) => write!(buf, "<mo stretchy=\"{stretchy}\">{op}</mo>"),
-        Node::OtherOperator(op) => write!(buf, "<mo>{op}</mo>"),
-        Node::SizedParen { size, paren } => {
-            write!(buf, "<mrow><mo maxsize=\"{size}\">{paren}</mo></mrow>")
-        }
-        Node::Text(text, _) => write!(buf, "<mtext>{text}</mtext>"),
-        Node::Matrix(content, align) => {
-            write!(buf, "<mtable align=\"{align}\"><mtr><mtd>")?;
-
-            match content.as_ref() {
-                Node::Row(nodes) => {
-                    for (i, node) in nodes.iter().enumerate() {
-                        match node {
-                            Node::NewLine => {
-                                write!(buf, "</mtd></mtr>")?;
-                                if i < nodes.len() {
-                                    write!(buf, "<mtr><mtd>")?;
-                                }
-                            }
-                            Node::Ampersand => {
-                                write!(buf, "</mtd>")?;
-                                if i < nodes.len() {
-                                    write!(buf, "<mtd>")?;
-                                }
-                            }
-                            node => expand_node(node, buf)?,
-                        }
-                    }
+        });
+    }
+
+    fn visit_fenced(&mut self, open: &'n Node<'a>, content: &'n Node<'a>, close: &'n Node<'a>) {
+        self.element("mrow", |this| {
+            this.visit_node(open);
+            this.visit_node(content);
+            this.visit_node(close);
+        });
+    }
+
+    fn visit_streched_op(&mut self, stretchy: bool, op: &'n &'a str) {
+        write!(self.out, "<mo stretchy=\"{stretchy}\">{op}</mo>").unwrap();
+    }
+
+    fn visit_other_operator(&mut self, op: &'n &'a str) {
+        write!(self.out, "<mo>{op}</mo>").unwrap();
+    }
+
+    fn visit_sized_paren(&mut self, size: &'n &'a str, paren: &'n &'a str) {
+        write!(self.out, "<mrow><mo maxsize=\"{size}\">{paren}</mo></mrow>").unwrap();
+    }
+
+    fn visit_text(&mut self, text: &'n &'a str, _variant: Variant) {
+        write!(self.out, "<mtext>{text}</mtext>").unwrap();
+    }
+
+    fn visit_matrix(&mut self, grid: &'n Grid<Node<'a>>) {
+        let columns: Vec<String> = grid.columns.iter().map(ToString::to_string).collect();
+        let columnlines = lines(grid.columns.len(), |i| grid.vlines.contains(&i));
+        // MathML only draws rules across the whole table
+        let rowlines = lines(grid.rows.len(), |i| {
+            grid.hlines
+                .iter()
+                .any(|hline| hline.row == i && hline.columns.is_none())
+        });
+        write!(
+            self.out,
+            "<mtable columnalign=\"{}\" columnlines=\"{}\" rowlines=\"{}\">",
+            columns.join(" "),
+            columnlines,
+            rowlines
+        )
+        .unwrap();
+        for (i, row) in grid.rows.iter().enumerate() {
+            self.push("<mtr>");
+            for (j, cell) in row.iter().enumerate() {
+                match grid.multicolumn(i, j) {
+                    Some(multicolumn) => write!(
+                        self.out,
+                        "<mtd columnspan=\"{}\" columnalign=\"{}\">",
+                        multicolumn.columns, multicolumn.align
+                    )
+                    .unwrap(),
+                    None => self.push("<mtd>"),
                 }
-                node => expand_node(node, buf)?,
+                self.visit_node(cell);
+                self.push("</mtd>");
             }
-            write!(buf, "</mtr></mtd></mtable>")
-        }
-        Node::Ampersand => write!(buf, "<mo>&#x0026;</mo>"),
-        Node::NewLine => write!(buf, "<mspace linebreak=\"newline\"/>"),
-        Node::Slashed(content) => {
-            write!(buf, "<menclose notation=\"updiagonalstrike\">")?;
-            expand_node(content, buf)?;
-            write!(buf, "</menclose>")
+            self.push("</mtr>");
         }
-        Node::Style(display, content) => {
-            match display {
-                DisplayStyle::Block => write!(buf, "<mstyle displaystyle=\"true\">"),
-                DisplayStyle::Inline => write!(buf, "<mstyle displaystyle=\"false\">"),
-            }?;
-            expand_node(content, buf)?;
-            write!(buf, "</mstyle>")
+        self.push("</mtable>");
+    }
+
+    fn visit_ampersand(&mut self) {
+        self.push("<mo>&#x0026;</mo>");
+    }
+
+    fn visit_new_line(&mut self) {
+        self.push("<mspace linebreak=\"newline\"/>");
+    }
+
+    fn visit_slashed(&mut self, content: &'n Node<'a>) {
+        self.push("<menclose notation=\"updiagonalstrike\">");
+        self.visit_node(content);
+        self.push("</menclose>");
+    }
+
+    fn visit_style(&mut self, style: DisplayStyle, content: &'n Node<'a>) {
+        match style {
+            DisplayStyle::Block => self.push("<mstyle displaystyle=\"true\">"),
+            DisplayStyle::Inline => self.push("<mstyle displaystyle=\"false\">"),
         }
-        Node::Undefined(token) => write!(buf, "<merror>{token:?}</merror>"),
-        _ => Ok(()),
+        self.visit_node(content);
+        self.push("</mstyle>");
+    }
+
+    fn visit_undefined(&mut self, token: &'n Token<'a>) {
+        write!(self.out, "<merror>{token:?}</merror>").unwrap();
     }
 }
+
+/// The `columnlines` or `rowlines` of an `mtable`, between `count` columns or rows
+fn lines(count: usize, ruled: impl Fn(usize) -> bool) -> String {
+    let lines: Vec<_> = (1..count)
+        .map(|i| if ruled(i) { "solid" } else { "none" })
+        .collect();
+    lines.join(" ")
+}
@@ -0,0 +1,763 @@
+use crate::{
+    models::{Accent, AtomClass, DisplayStyle, Grid, LineThickness, NodeOf, Space, Variant},
+    parser::{Shape, Sink},
+    token::TokenOf,
+};
+use alloc::{boxed::Box, vec::Vec};
+
+/// Index of a node in an [`Ast`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct NodeId(u32);
+
+impl NodeId {
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+/// Range of [`NodeId`]s listing the children of an [`AstNode::Row`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Children {
+    start: u32,
+    end: u32,
+}
+
+/// A node of an [`Ast`], mirroring [`Node`](crate::Node) with indices in place of boxes
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(tag = "type", content = "value"))]
+pub enum AstNode<S> {
+    Package(S),
+    Title(S),
+    Number(S),
+    Operator(S),
+    Text(S, Variant),
+    Letter(S, Variant),
+    Function(S, Option<NodeId>),
+    Space(Space),
+    Subscript(NodeId, NodeId),
+    Superscript(NodeId, NodeId),
+    SubSup {
+        target: NodeId,
+        sub: NodeId,
+        sup: NodeId,
+    },
+    OverOp(S, Accent, NodeId),
+    UnderOp(S, Accent, NodeId),
+    Overset {
+        over: NodeId,
+        target: NodeId,
+    },
+    Underset {
+        under: NodeId,
+        target: NodeId,
+    },
+    UnderOver {
+        target: NodeId,
+        under: NodeId,
+        over: NodeId,
+    },
+    Sqrt(Option<NodeId>, NodeId),
+    Frac(NodeId, NodeId, LineThickness),
+    Row(Children),
+    Fenced {
+        open: NodeId,
+        close: NodeId,
+        content: NodeId,
+    },
+    StrechedOp(bool, S),
+    OtherOperator(S),
+    SizedParen {
+        size: S,
+        paren: S,
+    },
+    Matrix(Box<Grid<NodeId>>),
+    Ampersand,
+    NewLine,
+    Slashed(NodeId),
+    Style(DisplayStyle, NodeId),
+    Atom(AtomClass, NodeId),
+    Undefined(TokenOf<S>),
+}
+
+/// A parsed document whose nodes all live in one vector and refer to their children by index
+///
+/// Nodes are stored in post-order, the order of [`SpanTable`](crate::SpanTable), so the span
+/// of a node converted from [`Parser::try_parse_spanned`](crate::Parser::try_parse_spanned) is
+/// the one at [`NodeId::index`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Ast<S> {
+    nodes: Vec<AstNode<S>>,
+    /// Children of every row, each row owning a contiguous range
+    links: Vec<NodeId>,
+    roots: Vec<NodeId>,
+}
+
+impl<S> Default for Ast<S> {
+    fn default() -> Self {
+        Self {
+            nodes: Vec::new(),
+            links: Vec::new(),
+            roots: Vec::new(),
+        }
+    }
+}
+
+impl<S> Ast<S> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Flattens `nodes` into an arena, keeping them as top level nodes
+    pub fn from_nodes(nodes: impl IntoIterator<Item = NodeOf<S>>) -> Self {
+        let mut ast = Self::new();
+        for node in nodes {
+            ast.push_root(node);
+        }
+        ast
+    }
+
+    /// Appends `node` and its descendants, as the last top level node
+    pub fn push_root(&mut self, node: NodeOf<S>) -> NodeId {
+        let mut pending = Vec::new();
+        let id = self.push(node, &mut pending);
+        self.roots.push(id);
+        id
+    }
+
+    /// Number of nodes in the arena, including the descendants of top level nodes
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    pub fn get(&self, id: NodeId) -> Option<NodeRef<'_, S>> {
+        (id.index() < self.nodes.len()).then_some(NodeRef { ast: self, id })
+    }
+
+    /// Top level nodes, in the order they appear in the source
+    pub fn roots(&self) -> impl Iterator<Item = NodeRef<'_, S>> {
+        self.roots.iter().map(|&id| NodeRef { ast: self, id })
+    }
+
+    /// All nodes, children before their parents
+    pub fn iter(&self) -> impl Iterator<Item = NodeRef<'_, S>> {
+        (0..self.nodes.len() as u32).map(|id| NodeRef {
+            ast: self,
+            id: NodeId(id),
+        })
+    }
+
+    /// Rebuilds the boxed top level nodes
+    pub fn to_nodes(&self) -> Vec<NodeOf<S>>
+    where
+        S: Clone,
+    {
+        self.roots().map(|node| node.to_node()).collect()
+    }
+
+    /// Pushes the children of `node` then `node` itself, `pending` collects the ids of row
+    /// children until their row is pushed
+    fn push(&mut self, node: NodeOf<S>, pending: &mut Vec<NodeId>) -> NodeId {
+        let mut push = |node: Box<NodeOf<S>>| self.push(*node, pending);
+        let node = match node {
+            NodeOf::Package(s) => AstNode::Package(s),
+            NodeOf::Title(s) => AstNode::Title(s),
+            NodeOf::Number(s) => AstNode::Number(s),
+            NodeOf::Operator(s) => AstNode::Operator(s),
+            NodeOf::Text(s, variant) => AstNode::Text(s, variant),
+            NodeOf::Letter(s, variant) => AstNode::Letter(s, variant),
+            NodeOf::Function(s, arg) => AstNode::Function(s, arg.map(push)),
+            NodeOf::Space(space) => AstNode::Space(space),
+            NodeOf::Subscript(target, script) => AstNode::Subscript(push(target), push(script)),
+            NodeOf::Superscript(target, script) => AstNode::Superscript(push(target), push(script)),
+            NodeOf::SubSup { target, sub, sup } => AstNode::SubSup {
+                target: push(target),
+                sub: push(sub),
+                sup: push(sup),
+            },
+            NodeOf::OverOp(s, accent, target) => AstNode::OverOp(s, accent, push(target)),
+            NodeOf::UnderOp(s, accent, target) => AstNode::UnderOp(s, accent, push(target)),
+            NodeOf::Overset { over, target } => AstNode::Overset {
+                over: push(over),
+                target: push(target),
+            },
+            NodeOf::Underset { under, target } => AstNode::Underset {
+                under: push(under),
+                target: push(target),
+            },
+            NodeOf::UnderOver {
+                target,
+                under,
+                over,
+            } => AstNode::UnderOver {
+                target: push(target),
+                under: push(under),
+                over: push(over),
+            },
+            NodeOf::Sqrt(degree, content) => {
+                let degree = degree.map(&mut push);
+                AstNode::Sqrt(degree, push(content))
+            }
+            NodeOf::Frac(numerator, denominator, thickness) => {
+                AstNode::Frac(push(numerator), push(denominator), thickness)
+            }
+            NodeOf::Row(nodes) => {
+                let mark = pending.len();
+                for node in nodes {
+                    let id = self.push(node, pending);
+                    pending.push(id);
+                }
+                let start = self.links.len() as u32;
+                self.links.extend(pending.drain(mark..));
+                AstNode::Row(Children {
+                    start,
+                    end: self.links.len() as u32,
+                })
+            }
+            NodeOf::Fenced {
+                open,
+                close,
+                content,
+            } => AstNode::Fenced {
+                open: push(open),
+                content: push(content),
+                close: push(close),
+            },
+            NodeOf::StrechedOp(stretchy, s) => AstNode::StrechedOp(stretchy, s),
+            NodeOf::OtherOperator(s) => AstNode::OtherOperator(s),
+            NodeOf::SizedParen { size, paren } => AstNode::SizedParen { size, paren },
+            NodeOf::Matrix(grid) => {
+                AstNode::Matrix(Box::new(grid.map(|node| self.push(node, pending))))
+            }
+            NodeOf::Ampersand => AstNode::Ampersand,
+            NodeOf::NewLine => AstNode::NewLine,
+            NodeOf::Slashed(content) => AstNode::Slashed(push(content)),
+            NodeOf::Style(style, content) => AstNode::Style(style, push(content)),
+            NodeOf::Atom(class, content) => AstNode::Atom(class, push(content)),
+            NodeOf::Undefined(token) => AstNode::Undefined(token),
+        };
+        let id = NodeId(self.nodes.len() as u32);
+        self.nodes.push(node);
+        id
+    }
+}
+
+/// Builds an [`Ast`] from the nodes handed over by the parser
+///
+/// Nodes are pushed as they are built, which is post-order unless a node is built before a
+/// sibling stored ahead of it or is dropped by the parser, in which case the top level node
+/// holding it is copied over in post-order once it is complete.
+pub(crate) struct AstBuilder<S> {
+    ast: Ast<S>,
+    /// First node of the subtree of every node pushed since the last top level node
+    starts: Vec<u32>,
+    /// First node pushed with its children out of post-order
+    disorder: Option<u32>,
+    /// Length of the nodes and links when the current top level node was started
+    root_start: u32,
+    links_start: u32,
+}
+
+impl<S> Default for AstBuilder<S> {
+    fn default() -> Self {
+        Self {
+            ast: Ast::new(),
+            starts: Vec::new(),
+            disorder: None,
+            root_start: 0,
+            links_start: 0,
+        }
+    }
+}
+
+impl<S: Clone> AstBuilder<S> {
+    /// Appends `root`, the last node built, as a top level node
+    pub(crate) fn push_root(&mut self, root: NodeId) {
+        let ordered = self.disorder.is_none()
+            && root.index() + 1 == self.ast.nodes.len()
+            && self.start(root) == self.root_start;
+        let root = if ordered { root } else { self.reorder(root) };
+        self.ast.roots.push(root);
+        self.root_start = self.ast.nodes.len() as u32;
+        self.links_start = self.ast.links.len() as u32;
+        self.starts.clear();
+    }
+
+    pub(crate) fn finish(self) -> Ast<S> {
+        self.ast
+    }
+
+    fn start(&self, id: NodeId) -> u32 {
+        self.starts[(id.0 - self.root_start) as usize]
+    }
+
+    /// Appends `node`, whose `children` are given in the order they are stored
+    fn append(&mut self, node: AstNode<S>, children: impl IntoIterator<Item = NodeId>) -> NodeId {
+        let id = self.ast.nodes.len() as u32;
+        let mut start = None;
+        let mut next = None;
+        for child in children {
+            let child_start = self.start(child);
+            if next.is_some_and(|next| next != child_start) {
+                self.disorder.get_or_insert(id);
+            }
+            start.get_or_insert(child_start);
+            next = Some(child.0 + 1);
+        }
+        if next.is_some_and(|next| next != id) {
+            self.disorder.get_or_insert(id);
+        }
+        self.starts.push(start.unwrap_or(id));
+        self.ast.nodes.push(node);
+        NodeId(id)
+    }
+
+    /// Drops the nodes pushed from `len` on, which are not referred to anymore
+    fn truncate(&mut self, len: u32) {
+        for node in self.ast.nodes.drain(len as usize..).rev() {
+            if let AstNode::Row(children) = node {
+                if children.end as usize == self.ast.links.len() {
+                    self.ast.links.truncate(children.start as usize);
+                }
+            }
+        }
+        self.starts.truncate((len - self.root_start) as usize);
+        if self.disorder.is_some_and(|id| id >= len) {
+            self.disorder = None;
+        }
+    }
+
+    /// Replaces the nodes pushed since the last top level node with a post-order copy of
+    /// `root`, leaving out the dropped ones
+    fn reorder(&mut self, root: NodeId) -> NodeId {
+        let mut copy = Ast::new();
+        self.copy(root, &mut copy, &mut Vec::new());
+        self.ast.nodes.truncate(self.root_start as usize);
+        self.ast.links.truncate(self.links_start as usize);
+        self.ast.nodes.append(&mut copy.nodes);
+        self.ast.links.append(&mut copy.links);
+        self.disorder = None;
+        NodeId(self.ast.nodes.len() as u32 - 1)
+    }
+
+    /// Copies `id` and its descendants in post-order into `copy`, whose nodes and links are
+    /// to be appended at the start of the current top level node
+    fn copy(&self, id: NodeId, copy: &mut Ast<S>, pending: &mut Vec<NodeId>) -> NodeId {
+        let mut push = |id: &NodeId| self.copy(*id, copy, pending);
+        let node = match &self.ast[id] {
+            AstNode::Function(s, arg) => AstNode::Function(s.clone(), arg.as_ref().map(push)),
+            AstNode::Subscript(target, script) => AstNode::Subscript(push(target), push(script)),
+            AstNode::Superscript(target, script) => {
+                AstNode::Superscript(push(target), push(script))
+            }
+            AstNode::SubSup { target, sub, sup } => AstNode::SubSup {
+                target: push(target),
+                sub: push(sub),
+                sup: push(sup),
+            },
+            AstNode::OverOp(s, accent, target) => AstNode::OverOp(s.clone(), *accent, push(target)),
+            AstNode::UnderOp(s, accent, target) => {
+                AstNode::UnderOp(s.clone(), *accent, push(target))
+            }
+            AstNode::Overset { over, target } => AstNode::Overset {
+                over: push(over),
+                target: push(target),
+            },
+            AstNode::Underset { under, target } => AstNode::Underset {
+                under: push(under),
+                target: push(target),
+            },
+            AstNode::UnderOver {
+                target,
+                under,
+                over,
+            } => AstNode::UnderOver {
+                target: push(target),
+                under: push(under),
+                over: push(over),
+            },
+            AstNode::Sqrt(degree, content) => {
+                let degree = degree.as_ref().map(&mut push);
+                AstNode::Sqrt(degree, push(content))
+            }
+            AstNode::Frac(numerator, denominator, thickness) => {
+                AstNode::Frac(push(numerator), push(denominator), *thickness)
+            }
+            AstNode::Row(children) => {
+                let mark = pending.len();
+                for &child in &self.ast.links[children.start as usize..children.end as usize] {
+                    let id = self.copy(child, copy, pending);
+                    pending.push(id);
+                }
+                let start = self.links_start + copy.links.len() as u32;
+                copy.links.extend(pending.drain(mark..));
+                AstNode::Row(Children {
+                    start,
+                    end: self.links_start + copy.links.len() as u32,
+                })
+            }
+            AstNode::Fenced {
+                open,
+                close,
+                content,
+            } => AstNode::Fenced {
+                open: push(open),
+                content: push(content),
+                close: push(close),
+            },
+            AstNode::Matrix(grid) => {
+                AstNode::Matrix(Box::new((**grid).clone().map(|id| push(&id))))
+            }
+            AstNode::Slashed(content) => AstNode::Slashed(push(content)),
+            AstNode::Style(style, content) => AstNode::Style(*style, push(content)),
+            AstNode::Atom(class, content) => AstNode::Atom(*class, push(content)),
+            leaf => leaf.clone(),
+        };
+        let id = NodeId(self.root_start + copy.nodes.len() as u32);
+        copy.nodes.push(node);
+        id
+    }
+}
+
+impl<'a> Sink<'a> for AstBuilder<&'a str> {
+    type Node = NodeId;
+
+    fn push(&mut self, shape: Shape<'a, NodeId>) -> NodeId {
+        match shape {
+            Shape::Leaf(node) => {
+                let start = self.ast.nodes.len() as u32;
+                let id = self.ast.push(node, &mut Vec::new());
+                self.starts
+                    .resize((id.0 + 1 - self.root_start) as usize, start);
+                id
+            }
+            Shape::Subscript(target, script) => {
+                self.append(AstNode::Subscript(target, script), [target, script])
+            }
+            Shape::Superscript(target, script) => {
+                self.append(AstNode::Superscript(target, script), [target, script])
+            }
+            Shape::SubSup { target, sub, sup } => {
+                self.append(AstNode::SubSup { target, sub, sup }, [target, sub, sup])
+            }
+            Shape::OverOp(s, accent, target) => {
+                self.append(AstNode::OverOp(s, accent, target), [target])
+            }
+            Shape::UnderOp(s, accent, target) => {
+                self.append(AstNode::UnderOp(s, accent, target), [target])
+            }
+            Shape::Overset { over, target } => {
+                self.append(AstNode::Overset { over, target }, [over, target])
+            }
+            Shape::Underset { under, target } => {
+                self.append(AstNode::Underset { under, target }, [under, target])
+            }
+            Shape::UnderOver {
+                target,
+                under,
+                over,
+            } => self.append(
+                AstNode::UnderOver {
+                    target,
+                    under,
+                    over,
+                },
+                [target, under, over],
+            ),
+            Shape::Sqrt(degree, content) => self.append(
+                AstNode::Sqrt(degree, content),
+                degree.into_iter().chain([content]),
+            ),
+            Shape::Frac(numerator, denominator, thickness) => self.append(
+                AstNode::Frac(numerator, denominator, thickness),
+                [numerator, denominator],
+            ),
+            Shape::Row(nodes) => {
+                let start = self.ast.links.len() as u32;
+                self.ast.links.extend(&nodes);
+                let children = Children {
+                    start,
+                    end: self.ast.links.len() as u32,
+                };
+                self.append(AstNode::Row(children), nodes)
+            }
+            Shape::Fenced {
+                open,
+                close,
+                content,
+            } => self.append(
+                AstNode::Fenced {
+                    open,
+                    close,
+                    content,
+                },
+                [open, content, close],
+            ),
+            Shape::Matrix(grid) => {
+                let children: Vec<_> = grid.children().copied().collect();
+                self.append(AstNode::Matrix(Box::new(grid)), children)
+            }
+            Shape::Slashed(content) => self.append(AstNode::Slashed(content), [content]),
+            Shape::Style(style, content) => self.append(AstNode::Style(style, content), [content]),
+            Shape::Atom(class, content) => self.append(AstNode::Atom(class, content), [content]),
+        }
+    }
+
+    fn unwrap_group(&mut self, node: NodeId) -> Result<NodeId, NodeId> {
+        let AstNode::Fenced {
+            open,
+            close,
+            content,
+        } = self.ast[node]
+        else {
+            return Err(node);
+        };
+        let brace = |id: NodeId, brace| self.ast[id] == AstNode::StrechedOp(true, brace);
+        if !brace(open, "{") || !brace(close, "}") {
+            return Err(node);
+        }
+        // The braces are built after the content, so the group is dropped along with them
+        if node.0 + 1 == self.ast.nodes.len() as u32
+            && close.0 + 1 == node.0
+            && open.0 + 1 == close.0
+        {
+            self.truncate(open.0);
+        }
+        Ok(content)
+    }
+
+    fn set_variant(&mut self, node: NodeId, var: Variant) -> NodeId {
+        match self.ast.nodes[node.index()] {
+            AstNode::Letter(_, ref mut variant) => *variant = var,
+            AstNode::Row(children) => {
+                for link in children.start..children.end {
+                    let child = self.ast.links[link as usize];
+                    self.set_variant(child, var);
+                }
+            }
+            _ => {}
+        }
+        node
+    }
+
+    fn is_empty_row(&self, node: &NodeId) -> bool {
+        matches!(self.ast[*node], AstNode::Row(children) if children.start == children.end)
+    }
+
+    fn discard(&mut self, node: NodeId) {
+        if node.0 + 1 == self.ast.nodes.len() as u32 && self.start(node) == node.0 {
+            self.truncate(node.0);
+        }
+    }
+}
+
+impl<S> FromIterator<NodeOf<S>> for Ast<S> {
+    fn from_iter<I: IntoIterator<Item = NodeOf<S>>>(nodes: I) -> Self {
+        Self::from_nodes(nodes)
+    }
+}
+
+impl<S> core::ops::Index<NodeId> for Ast<S> {
+    type Output = AstNode<S>;
+
+    fn index(&self, id: NodeId) -> &Self::Output {
+        &self.nodes[id.index()]
+    }
+}
+
+/// A node of an [`Ast`] along with the arena it lives in, see [`Node`](crate::Node) for the
+/// owned counterpart
+#[derive(Debug)]
+pub struct NodeRef<'t, S> {
+    ast: &'t Ast<S>,
+    id: NodeId,
+}
+
+impl<S> Clone for NodeRef<'_, S> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<S> Copy for NodeRef<'_, S> {}
+
+impl<'t, S> NodeRef<'t, S> {
+    pub fn id(self) -> NodeId {
+        self.id
+    }
+
+    pub fn kind(self) -> &'t AstNode<S> {
+        &self.ast[self.id]
+    }
+
+    /// Returns the `index`th child of the node, like [`Node::child`](crate::Node::child)
+    pub fn child(self, index: usize) -> Option<NodeRef<'t, S>> {
+        let id = match (self.kind(), index) {
+            (AstNode::Row(children), index) => {
+                let links = &self.ast.links[children.start as usize..children.end as usize];
+                *links.get(index)?
+            }
+            (AstNode::Function(_, Some(arg)), 0) => *arg,
+            (AstNode::Subscript(target, _), 0) | (AstNode::Superscript(target, _), 0) => *target,
+            (AstNode::Subscript(_, script), 1) | (AstNode::Superscript(_, script), 1) => *script,
+            (AstNode::SubSup { target, .. }, 0) => *target,
+            (AstNode::SubSup { sub, .. }, 1) => *sub,
+            (AstNode::SubSup { sup, .. }, 2) => *sup,
+            (AstNode::OverOp(_, _, target), 0) | (AstNode::UnderOp(_, _, target), 0) => *target,
+            (AstNode::Overset { over, .. }, 0) => *over,
+            (AstNode::Overset { target, .. }, 1) => *target,
+            (AstNode::Underset { under, .. }, 0) => *under,
+            (AstNode::Underset { target, .. }, 1) => *target,
+            (AstNode::UnderOver { target, .. }, 0) => *target,
+            (AstNode::UnderOver { under, .. }, 1) => *under,
+            (AstNode::UnderOver { over, .. }, 2) => *over,
+            (AstNode::Sqrt(Some(degree), _), 0) => *degree,
+            (AstNode::Sqrt(Some(_), content), 1) | (AstNode::Sqrt(None, content), 0) => *content,
+            (AstNode::Frac(numerator, _, _), 0) => *numerator,
+            (AstNode::Frac(_, denominator, _), 1) => *denominator,
+            (AstNode::Fenced { open, .. }, 0) => *open,
+            (AstNode::Fenced { content, .. }, 1) => *content,
+            (AstNode::Fenced { close, .. }, 2) => *close,
+            (AstNode::Matrix(grid), index) => *grid.child(index)?,
+            (AstNode::Slashed(content), 0)
+            | (AstNode::Style(_, content), 0)
+            | (AstNode::Atom(_, content), 0) => *content,
+            _ => return None,
+        };
+        Some(NodeRef { ast: self.ast, id })
+    }
+
+    /// Iterates over the direct children of the node, see [`NodeRef::child`]
+    pub fn children(self) -> impl Iterator<Item = NodeRef<'t, S>> {
+        // The cells of a matrix are walked row by row rather than looked up by index
+        let (cells, others) = match self.kind() {
+            AstNode::Matrix(grid) => (Some(grid.children()), None),
+            _ => (None, Some((0..).map_while(move |index| self.child(index)))),
+        };
+        let ast = self.ast;
+        let cells = cells
+            .into_iter()
+            .flatten()
+            .map(move |&id| NodeRef { ast, id });
+        cells.chain(others.into_iter().flatten())
+    }
+
+    /// Iterates depth first over the node and all of its descendants, parents before their
+    /// children
+    pub fn descendants(self) -> impl Iterator<Item = NodeRef<'t, S>> {
+        let mut stack = alloc::vec![self];
+        core::iter::from_fn(move || {
+            let node = stack.pop()?;
+            let start = stack.len();
+            stack.extend(node.children());
+            stack[start..].reverse();
+            Some(node)
+        })
+    }
+
+    /// Returns the inner string of the node, like [`Node::inner_str`](crate::Node::inner_str)
+    pub fn inner_str(self) -> Option<&'t str>
+    where
+        S: AsRef<str>,
+    {
+        let s = match self.kind() {
+            AstNode::Number(s)
+            | AstNode::Letter(s, _)
+            | AstNode::Operator(s)
+            | AstNode::Function(s, _)
+            | AstNode::OtherOperator(s)
+            | AstNode::StrechedOp(_, s)
+            | AstNode::UnderOp(s, _, _)
+            | AstNode::OverOp(s, _, _) => s,
+            AstNode::Text(s, _) | AstNode::SizedParen { paren: s, .. } => s,
+            _ => return None,
+        };
+        Some(s.as_ref())
+    }
+
+    /// Rebuilds the boxed tree rooted at this node
+    pub fn to_node(self) -> NodeOf<S>
+    where
+        S: Clone,
+    {
+        let ast = self.ast;
+        let node = |id: NodeId| NodeRef { ast, id }.to_node();
+        let boxed = |id: &NodeId| Box::new(node(*id));
+        match self.kind() {
+            AstNode::Package(s) => NodeOf::Package(s.clone()),
+            AstNode::Title(s) => NodeOf::Title(s.clone()),
+            AstNode::Number(s) => NodeOf::Number(s.clone()),
+            AstNode::Operator(s) => NodeOf::Operator(s.clone()),
+            AstNode::Text(s, variant) => NodeOf::Text(s.clone(), *variant),
+            AstNode::Letter(s, variant) => NodeOf::Letter(s.clone(), *variant),
+            AstNode::Function(s, arg) => NodeOf::Function(s.clone(), arg.as_ref().map(boxed)),
+            AstNode::Space(space) => NodeOf::Space(*space),
+            AstNode::Subscript(target, script) => NodeOf::Subscript(boxed(target), boxed(script)),
+            AstNode::Superscript(target, script) => {
+                NodeOf::Superscript(boxed(target), boxed(script))
+            }
+            AstNode::SubSup { target, sub, sup } => NodeOf::SubSup {
+                target: boxed(target),
+                sub: boxed(sub),
+                sup: boxed(sup),
+            },
+            AstNode::OverOp(s, accent, target) => NodeOf::OverOp(s.clone(), *accent, boxed(target)),
+            AstNode::UnderOp(s, accent, target) => {
+                NodeOf::UnderOp(s.clone(), *accent, boxed(target))
+            }
+            AstNode::Overset { over, target } => NodeOf::Overset {
+                over: boxed(over),
+                target: boxed(target),
+            },
+            AstNode::Underset { under, target } => NodeOf::Underset {
+                under: boxed(under),
+                target: boxed(target),
+            },
+            AstNode::UnderOver {
+                target,
+                under,
+                over,
+            } => NodeOf::UnderOver {
+                target: boxed(target),
+                under: boxed(under),
+                over: boxed(over),
+            },
+            AstNode::Sqrt(degree, content) => {
+                NodeOf::Sqrt(degree.as_ref().map(boxed), boxed(content))
+            }
+            AstNode::Frac(numerator, denominator, thickness) => {
+                NodeOf::Frac(boxed(numerator), boxed(denominator), *thickness)
+            }
+            AstNode::Row(_) => NodeOf::Row(self.children().map(NodeRef::to_node).collect()),
+            AstNode::Fenced {
+                open,
+                close,
+                content,
+            } => NodeOf::Fenced {
+                open: boxed(open),
+                close: boxed(close),
+                content: boxed(content),
+            },
+            AstNode::StrechedOp(stretchy, s) => NodeOf::StrechedOp(*stretchy, s.clone()),
+            AstNode::OtherOperator(s) => NodeOf::OtherOperator(s.clone()),
+            AstNode::SizedParen { size, paren } => NodeOf::SizedParen {
+                size: size.clone(),
+                paren: paren.clone(),
+            },
+            AstNode::Matrix(grid) => NodeOf::Matrix(Box::new((**grid).clone().map(node))),
+            AstNode::Ampersand => NodeOf::Ampersand,
+            AstNode::NewLine => NodeOf::NewLine,
+            AstNode::Slashed(content) => NodeOf::Slashed(boxed(content)),
+            AstNode::Style(style, content) => NodeOf::Style(*style, boxed(content)),
+            AstNode::Atom(class, content) => NodeOf::Atom(*class, boxed(content)),
+            AstNode::Undefined(token) => NodeOf::Undefined(token.clone()),
+        }
+    }
+}
@@ -0,0 +1,184 @@
+use crate::{
+    span::Span,
+    token::{closest_command, Token},
+};
+use core::fmt;
+
+/// What went wrong while parsing
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParseErrorKind<'a> {
+    /// A `\left` or `\begin` group reached the end of the input before it was closed
+    UnterminatedGroup,
+    /// `\end{..}` names a different environment than the `\begin{..}` it closes
+    MismatchedEnvironment { begin: &'a str, end: &'a str },
+    /// `\left`, `\right` or `\middle` was not followed by a delimiter
+    InvalidDelimiter,
+    /// The input ended before a command received all of its arguments
+    MissingArgument,
+    /// A `\right` or `\end` that does not close any group
+    UnexpectedToken,
+    /// A command that is neither built in nor defined in the input
+    UnknownCommand(&'a str),
+    /// A `\newcommand`, `\renewcommand` or `\def` that could not be read
+    InvalidDefinition,
+    /// `\newcommand` of a command that already exists, use `\renewcommand` instead
+    AlreadyDefined(&'a str),
+    /// Macros expanded into each other too deeply, usually because one is recursive
+    MacroDepth,
+    /// A length such as the `2pt` of `\\[2pt]` that could not be read
+    InvalidDimension(&'a str),
+    /// A column specification of an `array`, `\multicolumn` or `\cline` that could not be read
+    InvalidColumnSpec(&'a str),
+}
+
+impl fmt::Display for ParseErrorKind<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseErrorKind::UnterminatedGroup => write!(f, "unterminated group"),
+            ParseErrorKind::MismatchedEnvironment { begin, end } => {
+                write!(f, "\\begin{{{begin}}} closed by \\end{{{end}}}")
+            }
+            ParseErrorKind::InvalidDelimiter => write!(f, "invalid delimiter"),
+            ParseErrorKind::MissingArgument => write!(f, "missing argument"),
+            ParseErrorKind::UnexpectedToken => write!(f, "unexpected token"),
+            ParseErrorKind::UnknownCommand(command) => write!(f, "unknown command `\\{command}`"),
+            ParseErrorKind::InvalidDefinition => write!(f, "invalid macro definition"),
+            ParseErrorKind::AlreadyDefined(command) => {
+                write!(f, "command `\\{command}` already defined")
+            }
+            ParseErrorKind::MacroDepth => write!(f, "macro expansion too deep"),
+            ParseErrorKind::InvalidDimension(text) => write!(f, "invalid dimension `{text}`"),
+            ParseErrorKind::InvalidColumnSpec(text) => {
+                write!(f, "invalid column specification `{text}`")
+            }
+        }
+    }
+}
+
+/// Error returned by [`Parser::try_parse`](crate::Parser::try_parse)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParseError<'a> {
+    pub kind: ParseErrorKind<'a>,
+    /// Location of the offending token in the input
+    pub span: Span,
+    /// The token the parser was looking for, if there was a single one
+    pub expected: Option<Token<'a>>,
+    pub found: Token<'a>,
+}
+
+impl fmt::Display for ParseError<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at {}..{}", self.kind, self.span.start, self.span.end)?;
+        match self.expected {
+            Some(expected) => write!(f, ": expected {expected:?}, found {:?}", self.found),
+            None => write!(f, ": found {:?}", self.found),
+        }
+    }
+}
+
+impl core::error::Error for ParseError<'_> {}
+
+impl ParseError<'_> {
+    /// A known command the unknown one was probably meant to be
+    pub fn suggestion(&self) -> Option<&'static str> {
+        match self.kind {
+            ParseErrorKind::UnknownCommand(command) => closest_command(command),
+            _ => None,
+        }
+    }
+
+    /// Writes a report of the error to `out`, quoting the line of `input` it was found on
+    /// and underlining the offending span
+    ///
+    /// ```text
+    /// error: unknown command `\mathbbb`
+    ///  --> 1:5
+    ///   |
+    /// 1 | x + \mathbbb{A}
+    ///   |     ^^^^^^^^
+    ///   = help: did you mean `\mathbb`?
+    /// ```
+    pub fn render(&self, input: &str, out: &mut impl fmt::Write) -> fmt::Result {
+        let offset = self.span.start.min(input.len());
+        let line_start = input[..offset].rfind('\n').map_or(0, |i| i + 1);
+        let line_end = input[offset..]
+            .find('\n')
+            .map_or(input.len(), |i| offset + i);
+        let line = &input[line_start..line_end];
+        let number = input[..line_start].matches('\n').count() + 1;
+        let column = input[line_start..offset].chars().count() + 1;
+        let underline = input[offset..self.span.end.clamp(offset, line_end)]
+            .chars()
+            .count()
+            .max(1);
+        let gutter = number.ilog10() as usize + 1;
+
+        writeln!(out, "error: {}", self.kind)?;
+        writeln!(out, "{:gutter$}--> {number}:{column}", "")?;
+        writeln!(out, "{:gutter$} |", "")?;
+        writeln!(out, "{number} | {line}")?;
+        writeln!(
+            out,
+            "{:gutter$} | {:column$}{:^<underline$}",
+            "",
+            "",
+            "",
+            column = column - 1
+        )?;
+        match (
+            self.kind,
+            self.suggestion(),
+            self.expected.and_then(spelling),
+        ) {
+            (_, Some(command), _) => {
+                writeln!(out, "{:gutter$} = help: did you mean `\\{command}`?", "")
+            }
+            (ParseErrorKind::MismatchedEnvironment { begin, .. }, ..) => {
+                writeln!(out, "{:gutter$} = help: expected `\\end{{{begin}}}`", "")
+            }
+            (_, _, Some(expected)) => writeln!(out, "{:gutter$} = help: expected `{expected}`", ""),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// How `token` is written in the input, for the tokens the parser expects
+fn spelling(token: Token<'_>) -> Option<&str> {
+    match token {
+        Token::Right => Some("\\right"),
+        Token::End => Some("\\end"),
+        Token::Paren(paren) | Token::RSeperator(paren) => Some(paren),
+        _ => None,
+    }
+}
+
+/// How the parser got past an error when recovering from it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Recovery {
+    /// The open group was closed where the error occurred
+    ClosedGroup,
+    /// The offending tokens were dropped
+    Skipped,
+    /// A placeholder node was built in place of the malformed input
+    Substituted,
+}
+
+/// An error the parser recovered from, see [`Parser::parse_recovering`](crate::Parser::parse_recovering)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Diagnostic<'a> {
+    pub error: ParseError<'a>,
+    pub recovery: Recovery,
+}
+
+impl Diagnostic<'_> {
+    /// Writes a report of the error, see [`ParseError::render`]
+    pub fn render(&self, input: &str, out: &mut impl fmt::Write) -> fmt::Result {
+        self.error.render(input, out)
+    }
+}
+
+impl fmt::Display for Diagnostic<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.error.fmt(f)
+    }
+}
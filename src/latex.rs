@@ -0,0 +1,754 @@
+use crate::{
+    lexer::Lexer,
+    models::{ColumnAlign, Dimension, DisplayStyle, Grid, LineThickness, NodeOf, Variant},
+    parser::closing,
+    symbols::SYMBOLS,
+    token::Token,
+};
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+
+/// Environments whose matrix is fenced by a pair of delimiters
+const FENCED_MATRICES: &[(&str, &str, &str)] = &[
+    ("pmatrix", "(", ")"),
+    ("bmatrix", "[", "]"),
+    ("vmatrix", "|", "|"),
+    ("Bmatrix", "{", "}"),
+    ("Vmatrix", "║", "║"),
+];
+
+/// Writes `nodes` back as LaTeX source, which [`Parser`](crate::Parser) reads into `nodes` again
+///
+/// This holds for the trees the parser produces, except where it reads the source otherwise
+/// than TeX: a row of letters in a style along with other nodes is written as a group, since a
+/// style would also change its digits. Other trees, such as rewritten ones, are written as the
+/// source TeX reads as them, which may read back differently.
+pub fn to_latex<S: AsRef<str>>(nodes: &[NodeOf<S>]) -> String {
+    let mut writer = Writer::default();
+    writer.sequence(nodes);
+    writer.out
+}
+
+impl<S: AsRef<str>> NodeOf<S> {
+    /// Writes the node back as LaTeX source, see [`to_latex`]
+    pub fn to_latex(&self) -> String {
+        let mut writer = Writer::default();
+        writer.node(self);
+        writer.out
+    }
+}
+
+#[derive(Default)]
+struct Writer {
+    out: String,
+}
+
+impl Writer {
+    fn push(&mut self, text: &str) {
+        self.out.push_str(text);
+    }
+
+    /// Nodes one after the other, as in a row or at the top level
+    fn sequence<S: AsRef<str>>(&mut self, nodes: &[NodeOf<S>]) {
+        for (i, node) in nodes.iter().enumerate() {
+            if i > 0 {
+                self.push(" ");
+            }
+            self.node(node);
+        }
+    }
+
+    /// The content of a group, which reads back as a row when it holds several nodes
+    fn group<S: AsRef<str>>(&mut self, node: &NodeOf<S>) {
+        match node {
+            NodeOf::Row(nodes) if nodes.len() > 1 => self.sequence(nodes),
+            NodeOf::Row(nodes) if nodes.is_empty() => {}
+            node => self.node(node),
+        }
+    }
+
+    /// An argument in braces, which the parser unwraps
+    fn arg<S: AsRef<str>>(&mut self, node: &NodeOf<S>) {
+        self.push("{");
+        self.group(node);
+        self.push("}");
+    }
+
+    fn node<S: AsRef<str>>(&mut self, node: &NodeOf<S>) {
+        match node {
+            NodeOf::Package(name) => {
+                self.push("\\usepackage");
+                self.text(name.as_ref());
+            }
+            NodeOf::Title(title) => {
+                self.push("\\title");
+                self.text(title.as_ref());
+            }
+            NodeOf::Number(number) => self.push(number.as_ref()),
+            NodeOf::Operator(op) => self.operator(op.as_ref()),
+            NodeOf::Text(text, _) => {
+                self.push("\\text");
+                self.text(text.as_ref());
+            }
+            NodeOf::Letter(letter, variant) => self.letter(letter.as_ref(), *variant),
+            NodeOf::Function(name, arg) => {
+                self.function(name.as_ref());
+                if let Some(arg) = arg {
+                    self.push(" ");
+                    self.node(arg);
+                }
+            }
+            NodeOf::Space(space) => match command(Token::Space(*space)) {
+                Some(command) => self.command(command),
+                None => self.push(&format!("\\hspace{{{space}}}")),
+            },
+            NodeOf::Subscript(target, sub) => {
+                self.node(target);
+                self.push("_");
+                self.arg(sub);
+            }
+            NodeOf::Superscript(target, sup) if is_prime(sup) => {
+                self.node(target);
+                self.push("'");
+            }
+            NodeOf::Superscript(target, sup) => {
+                self.node(target);
+                self.push("^");
+                self.arg(sup);
+            }
+            NodeOf::SubSup { target, sub, sup } => {
+                self.node(target);
+                self.push("_");
+                self.arg(sub);
+                self.push("^");
+                self.arg(sup);
+            }
+            NodeOf::OverOp(op, accent, target) => {
+                let op = op.as_ref();
+                match command(Token::Over(op, *accent)) {
+                    Some(command) => {
+                        self.command(command);
+                        self.token_arg(target);
+                    }
+                    // A big operator with only an upper limit
+                    None => {
+                        self.operator(op);
+                        self.push("^");
+                        self.arg(target);
+                    }
+                }
+            }
+            NodeOf::UnderOp(op, accent, target) => {
+                match command(Token::Under(op.as_ref(), *accent)) {
+                    Some(command) => self.command(command),
+                    None => self.push("\\underline"),
+                }
+                self.token_arg(target);
+            }
+            NodeOf::Overset { over, target } => {
+                if let Some(brace) = brace(over, Token::Overbrace) {
+                    self.command(brace);
+                    self.arg(target);
+                } else if let NodeOf::Overset {
+                    over: label,
+                    target: brace_op,
+                } = &**over
+                {
+                    match brace(brace_op, Token::Overbrace) {
+                        Some(brace) => {
+                            self.command(brace);
+                            self.arg(target);
+                            self.push("^");
+                            self.arg(label);
+                        }
+                        None => self.set("\\overset", over, target),
+                    }
+                } else if self.limits(target, false) {
+                    self.push("^");
+                    self.arg(over);
+                } else {
+                    self.set("\\overset", over, target);
+                }
+            }
+            NodeOf::Underset { under, target } => {
+                if let Some(brace) = brace(under, Token::Underbrace) {
+                    self.command(brace);
+                    self.arg(target);
+                } else if let NodeOf::Underset {
+                    under: label,
+                    target: brace_op,
+                } = &**under
+                {
+                    match brace(brace_op, Token::Underbrace) {
+                        Some(brace) => {
+                            self.command(brace);
+                            self.arg(target);
+                            self.push("_");
+                            self.arg(label);
+                        }
+                        None => self.set("\\underset", under, target),
+                    }
+                } else if self.limits(target, true) {
+                    self.push("_");
+                    self.arg(under);
+                } else {
+                    self.set("\\underset", under, target);
+                }
+            }
+            NodeOf::UnderOver {
+                target,
+                under,
+                over,
+            } => {
+                if self.limits(target, true) {
+                    self.push("_");
+                    self.arg(under);
+                    self.push("^");
+                    self.arg(over);
+                } else {
+                    self.push("\\underset");
+                    self.arg(under);
+                    self.push("{");
+                    self.set("\\overset", over, target);
+                    self.push("}");
+                }
+            }
+            NodeOf::Sqrt(degree, content) => {
+                self.push("\\sqrt");
+                if let Some(degree) = degree {
+                    self.push("[");
+                    self.group(degree);
+                    self.push("]");
+                }
+                self.token_arg(content);
+            }
+            NodeOf::Frac(numerator, denominator, _) => {
+                self.push("\\frac");
+                self.arg(numerator);
+                self.arg(denominator);
+            }
+            NodeOf::Row(nodes) => match operator_name(nodes) {
+                Some(name) => {
+                    self.push("\\operatorname");
+                    self.text(&name);
+                }
+                None => self.row(nodes),
+            },
+            NodeOf::Fenced {
+                open,
+                close,
+                content,
+            } => self.fenced(open, content, close),
+            NodeOf::StrechedOp(true, op) => {
+                self.push("\\middle");
+                self.delimiter(op.as_ref());
+            }
+            NodeOf::StrechedOp(false, op) | NodeOf::OtherOperator(op) => self.operator(op.as_ref()),
+            NodeOf::SizedParen { size, paren } => {
+                self.size(size.as_ref());
+                self.delimiter(paren.as_ref());
+            }
+            NodeOf::Matrix(grid) => self.matrix(grid),
+            NodeOf::Ampersand => self.push("&"),
+            NodeOf::NewLine => self.push("\\\\"),
+            NodeOf::Slashed(content) => {
+                self.push("\\slashed");
+                self.arg(content);
+            }
+            NodeOf::Style(style, content) => match binom(content) {
+                Some((numerator, denominator)) => {
+                    let command = command(Token::Binom(Some(*style)));
+                    self.command(command.unwrap_or("binom"));
+                    self.push(" ");
+                    self.node(numerator);
+                    self.push(" ");
+                    self.node(denominator);
+                }
+                None => {
+                    self.push(match style {
+                        DisplayStyle::Block => "{\\displaystyle ",
+                        DisplayStyle::Inline => "{\\textstyle ",
+                    });
+                    self.group(content);
+                    self.push("}");
+                }
+            },
+            NodeOf::Atom(class, content) => {
+                self.command(command(Token::Atom(*class)).unwrap_or("mathord"));
+                self.arg(content);
+            }
+            NodeOf::Undefined(token) => {
+                let token = token.as_ref().map_text(AsRef::as_ref);
+                match (token, command(token)) {
+                    (Token::Command(name), _) | (_, Some(name)) => self.command(name),
+                    (Token::Circumflex, None) => self.push("^"),
+                    (Token::Underscore, None) => self.push("_"),
+                    (Token::Parameter(n), None) => self.push(&format!("#{n}")),
+                    (token, None) => self.push(token.to_str().unwrap_or_default()),
+                }
+            }
+        }
+    }
+
+    fn command(&mut self, name: &str) {
+        self.push("\\");
+        self.push(name);
+    }
+
+    /// Text read as is up to the closing brace
+    fn text(&mut self, text: &str) {
+        self.push("{");
+        self.push(text);
+        self.push("}");
+    }
+
+    fn set<S: AsRef<str>>(&mut self, command: &str, script: &NodeOf<S>, target: &NodeOf<S>) {
+        self.push(command);
+        self.arg(script);
+        self.arg(target);
+    }
+
+    fn operator(&mut self, op: &str) {
+        let tokens = [Token::Operator(op), Token::BigOp(op), Token::Integral(op)];
+        if let Some(symbol) = tokens.into_iter().find_map(symbol) {
+            return self.push(&symbol);
+        }
+        // An empty group reads as its opening delimiter
+        match lex(op) {
+            Some(Token::LSeperator(open)) => {
+                if let Some(close) =
+                    closing(open).and_then(|close| symbol(Token::RSeperator(close)))
+                {
+                    self.push(op);
+                    self.push(&close);
+                    return;
+                }
+            }
+            Some(Token::Paren("|")) => return self.push("||"),
+            _ => {}
+        }
+        self.push(op);
+    }
+
+    fn letter(&mut self, letter: &str, variant: Variant) {
+        if let Some(symbol) = symbol(Token::Letter(letter, variant)) {
+            return self.push(&symbol);
+        }
+        // The letter in its default variant, restyled
+        let plain = match lex(letter) {
+            Some(token @ Token::Letter(..)) => symbol(token),
+            _ => SYMBOLS.iter().find_map(|symbol| match symbol.token {
+                Token::Letter(glyph, _) if glyph == letter => Some(format!("\\{}", symbol.command)),
+                _ => None,
+            }),
+        };
+        let plain = plain.unwrap_or_else(|| letter.to_string());
+        match command(Token::Style(variant)) {
+            Some(style) => {
+                self.command(style);
+                self.text(&plain);
+            }
+            None => self.push(&plain),
+        }
+    }
+
+    fn function(&mut self, name: &str) {
+        match symbol(Token::Function(name)).or_else(|| symbol(Token::Lim(name))) {
+            Some(symbol) => self.push(&symbol),
+            None => {
+                self.push("\\operatorname");
+                self.text(name);
+            }
+        }
+    }
+
+    /// Writes `target` if its scripts are placed under and over it, as for `\lim` or `\sum`
+    /// when `big_op` is set
+    fn limits<S: AsRef<str>>(&mut self, target: &NodeOf<S>, big_op: bool) -> bool {
+        match target {
+            NodeOf::Function(name, None) => match symbol(Token::Lim(name.as_ref())) {
+                Some(symbol) => self.push(&symbol),
+                None => {
+                    self.push("\\operatorname*");
+                    self.text(name.as_ref());
+                }
+            },
+            NodeOf::Row(nodes) => match operator_name(nodes) {
+                Some(name) => {
+                    self.push("\\operatorname*");
+                    self.text(&name);
+                }
+                None => return false,
+            },
+            NodeOf::Operator(op) if big_op => match symbol(Token::BigOp(op.as_ref())) {
+                Some(symbol) => self.push(&symbol),
+                None => return false,
+            },
+            _ => return false,
+        }
+        true
+    }
+
+    /// A row on its own rather than as the content of a group, which a style reads back as
+    /// when the row only holds letters in that style
+    fn row<S: AsRef<str>>(&mut self, nodes: &[NodeOf<S>]) {
+        let mut variants = Vec::new();
+        if letter_variants(nodes, &mut variants) {
+            if let [variant] = variants[..] {
+                if let Some(style) = command(Token::Style(variant)) {
+                    self.command(style);
+                }
+            }
+        }
+        self.push("{");
+        self.sequence(nodes);
+        self.push("}");
+    }
+
+    /// The argument of a command such as `\sqrt` or `\hat`, braced unless it is written as a
+    /// single token, which is all TeX takes without braces
+    ///
+    /// A group is braced again, since the parser unwraps the braces of the argument.
+    fn token_arg<S: AsRef<str>>(&mut self, node: &NodeOf<S>) {
+        let mut writer = Writer::default();
+        writer.node(node);
+        if !is_group(node) && single_token(&writer.out) {
+            self.push(" ");
+            self.push(&writer.out);
+        } else {
+            self.arg(node);
+        }
+    }
+
+    fn fenced<S: AsRef<str>>(&mut self, open: &NodeOf<S>, content: &NodeOf<S>, close: &NodeOf<S>) {
+        let delimiters = match (open, close) {
+            (NodeOf::StrechedOp(true, open), NodeOf::StrechedOp(true, close)) => {
+                Some((open.as_ref(), close.as_ref()))
+            }
+            _ => None,
+        };
+        if let Some((open, close)) = delimiters {
+            if let (("(", ")"), Some((numerator, denominator))) = ((open, close), binom(content)) {
+                self.push("\\binom ");
+                self.node(numerator);
+                self.push(" ");
+                self.node(denominator);
+                return;
+            }
+            if let NodeOf::Matrix(grid) = content {
+                let environment = FENCED_MATRICES
+                    .iter()
+                    .find(|&&(_, o, c)| (o, c) == (open, close));
+                if let (Some((environment, _, _)), Some("matrix")) = (environment, layout(grid)) {
+                    return self.environment(environment, grid);
+                }
+            }
+            let empty = matches!(content, NodeOf::Row(nodes) if nodes.len() < 2);
+            let group = closing(open)
+                .filter(|&closer| closer == close)
+                .and_then(|_| {
+                    Some((
+                        symbol(Token::LSeperator(open)).or_else(|| symbol(Token::Paren(open)))?,
+                        symbol(Token::RSeperator(close)).or_else(|| symbol(Token::Paren(close)))?,
+                    ))
+                });
+            if let (Some((open, close)), false) = (group, empty) {
+                self.push(&open);
+                self.push(" ");
+                self.group(content);
+                self.push(" ");
+                self.push(&close);
+                return;
+            }
+        }
+        if let (NodeOf::StrechedOp(false, open), NodeOf::StrechedOp(false, close)) = (open, close) {
+            self.delimiter(open.as_ref());
+            if !matches!(content, NodeOf::Row(nodes) if nodes.is_empty()) {
+                self.push(" ");
+                self.group(content);
+            }
+            self.push(" ");
+            return self.delimiter(close.as_ref());
+        }
+        self.push("\\left");
+        self.fence(open);
+        self.push(" ");
+        self.group(content);
+        self.push(" \\right");
+        self.fence(close);
+    }
+
+    /// The delimiter of a `\left` or `\right`
+    fn fence<S: AsRef<str>>(&mut self, fence: &NodeOf<S>) {
+        match fence {
+            NodeOf::StrechedOp(_, paren) => self.delimiter(paren.as_ref()),
+            NodeOf::SizedParen { size, paren } => {
+                self.size(size.as_ref());
+                self.delimiter(paren.as_ref());
+            }
+            _ => self.push("."),
+        }
+    }
+
+    fn delimiter(&mut self, paren: &str) {
+        let tokens = [
+            Token::Paren(paren),
+            Token::LSeperator(paren),
+            Token::RSeperator(paren),
+            Token::Operator(paren),
+        ];
+        match tokens.into_iter().find_map(symbol) {
+            Some(symbol) if symbol.starts_with('\\') => self.push(&symbol),
+            Some(symbol) => {
+                self.push(" ");
+                self.push(&symbol);
+            }
+            None if paren.is_empty() => self.push("."),
+            None => self.push(paren),
+        }
+    }
+
+    fn size(&mut self, size: &str) {
+        self.command(command(Token::Big(size)).unwrap_or("big"));
+    }
+
+    fn matrix<S: AsRef<str>>(&mut self, grid: &Grid<NodeOf<S>>) {
+        self.environment(layout(grid).unwrap_or("array"), grid)
+    }
+
+    fn environment<S: AsRef<str>>(&mut self, environment: &str, grid: &Grid<NodeOf<S>>) {
+        self.push("\\begin");
+        self.text(environment);
+        if environment == "array" {
+            self.column_spec(&grid.columns, &grid.widths, &grid.vlines, &grid.separators);
+        }
+        for (i, row) in grid.rows.iter().enumerate() {
+            self.rules(grid, i);
+            for (j, cell) in row.iter().enumerate() {
+                self.push(if j == 0 { " " } else { " & " });
+                match grid.multicolumn(i, j) {
+                    Some(multicolumn) => {
+                        self.push(&format!("\\multicolumn{{{}}}", multicolumn.columns));
+                        self.column_spec::<S>(
+                            &[multicolumn.align],
+                            &[multicolumn.width],
+                            &multicolumn.vlines,
+                            &[],
+                        );
+                        self.arg(cell);
+                    }
+                    None => self.group(cell),
+                }
+            }
+            let spacing = grid.row_spacing.get(i).copied().flatten();
+            // A last row left empty would be dropped without its `\\`, and a rule below the
+            // last row is written after one
+            let empty = matches!(&row[..], [NodeOf::Row(nodes)] if nodes.is_empty());
+            let ruled = grid.hlines.iter().any(|hline| hline.row == grid.rows.len());
+            if i + 1 < grid.rows.len() || spacing.is_some() || empty || ruled {
+                self.push(" \\\\");
+            }
+            if let Some(spacing) = spacing {
+                self.push(&format!("[{spacing}]"));
+            }
+        }
+        self.rules(grid, grid.rows.len());
+        self.push(" \\end");
+        self.text(environment);
+    }
+
+    /// The column specification of an `array` or `\multicolumn`, such as `{c|c|l}`
+    fn column_spec<S: AsRef<str>>(
+        &mut self,
+        columns: &[ColumnAlign],
+        widths: &[Option<Dimension>],
+        vlines: &[usize],
+        separators: &[(usize, NodeOf<S>)],
+    ) {
+        self.push("{");
+        for column in 0..=columns.len() {
+            for _ in vlines.iter().filter(|&&vline| vline == column) {
+                self.push("|");
+            }
+            for (_, separator) in separators.iter().filter(|(at, _)| *at == column) {
+                self.push("@");
+                self.arg(separator);
+            }
+            match (columns.get(column), widths.get(column).copied().flatten()) {
+                (None, _) => {}
+                (Some(_), Some(width)) => self.push(&format!("p{{{width}}}")),
+                (Some(ColumnAlign::Left), None) => self.push("l"),
+                (Some(ColumnAlign::Center), None) => self.push("c"),
+                (Some(ColumnAlign::Right), None) => self.push("r"),
+            }
+        }
+        self.push("}");
+    }
+
+    /// The `\hline` and `\cline` rules drawn above `row`
+    fn rules<C>(&mut self, grid: &Grid<C>, row: usize) {
+        for hline in grid.hlines.iter().filter(|hline| hline.row == row) {
+            match hline.columns {
+                Some((first, last)) => {
+                    self.push(&format!(" \\cline{{{}-{}}}", first + 1, last + 1));
+                }
+                None => self.push(" \\hline"),
+            }
+        }
+    }
+}
+
+/// The environment of the matrices `grid` can be written as, `None` for an `array`
+fn layout<C>(grid: &Grid<C>) -> Option<&'static str> {
+    let count = grid.rows.iter().map(Vec::len).max().unwrap_or(0);
+    let plain = grid.columns.len() == count
+        && grid.widths.iter().all(Option::is_none)
+        && grid.vlines.is_empty()
+        && grid.separators.is_empty();
+    let aligned = grid
+        .columns
+        .iter()
+        .enumerate()
+        .all(|(i, &align)| align == [ColumnAlign::Right, ColumnAlign::Left][i % 2]);
+    let centered = grid
+        .columns
+        .iter()
+        .all(|&align| align == ColumnAlign::Center);
+    match (plain, aligned, centered) {
+        (true, _, true) => Some("matrix"),
+        (true, true, _) => Some("aligned"),
+        _ => None,
+    }
+}
+
+/// The numerator and denominator of the fraction of a `\binom`
+fn binom<S: AsRef<str>>(node: &NodeOf<S>) -> Option<(&NodeOf<S>, &NodeOf<S>)> {
+    match node {
+        NodeOf::Frac(numerator, denominator, LineThickness::Length(0)) => {
+            Some((numerator, denominator))
+        }
+        NodeOf::Fenced {
+            open,
+            close,
+            content,
+        } if matches!((&**open, &**close), (NodeOf::StrechedOp(true, o), NodeOf::StrechedOp(true, c))
+                if o.as_ref() == "(" && c.as_ref() == ")") =>
+        {
+            binom(content)
+        }
+        _ => None,
+    }
+}
+
+fn is_prime<S: AsRef<str>>(node: &NodeOf<S>) -> bool {
+    matches!(node, NodeOf::Operator(op) if op.as_ref() == "′")
+}
+
+/// The command of an `\overbrace` like `brace` token, if `node` is its glyph
+fn brace<'n, S: AsRef<str>>(
+    node: &'n NodeOf<S>,
+    brace: fn(&'n str) -> Token<'n>,
+) -> Option<&'static str> {
+    match node {
+        NodeOf::Operator(glyph) => command(brace(glyph.as_ref())),
+        _ => None,
+    }
+}
+
+/// Collects the variants of the letters of a row, returns whether it only holds letters
+fn letter_variants<S>(nodes: &[NodeOf<S>], variants: &mut Vec<Variant>) -> bool {
+    nodes.iter().all(|node| match node {
+        NodeOf::Letter(_, variant) => {
+            if !variants.contains(variant) {
+                variants.push(*variant);
+            }
+            true
+        }
+        NodeOf::Row(nodes) => letter_variants(nodes, variants),
+        _ => false,
+    })
+}
+
+/// The name of an operator such as `arg\,max`, which the parser reads as a row of names and
+/// spaces
+fn operator_name<S: AsRef<str>>(nodes: &[NodeOf<S>]) -> Option<String> {
+    let mut name = String::new();
+    for node in nodes {
+        match node {
+            NodeOf::Function(text, None) => name.push_str(text.as_ref()),
+            NodeOf::Space(space) => {
+                let command = command(Token::Space(*space))?;
+                name.push('\\');
+                name.push_str(command);
+                if command.chars().all(|c| c.is_ascii_alphabetic()) {
+                    name.push(' ');
+                }
+            }
+            _ => return None,
+        }
+    }
+    let named = nodes
+        .iter()
+        .any(|node| matches!(node, NodeOf::Function(..)));
+    named.then_some(name)
+}
+
+/// Whether `node` is a `{}` group, as the parser reads one
+fn is_group<S: AsRef<str>>(node: &NodeOf<S>) -> bool {
+    matches!(
+        node,
+        NodeOf::Fenced { open, close, .. }
+            if matches!((&**open, &**close), (NodeOf::StrechedOp(true, o), NodeOf::StrechedOp(true, c))
+                if o.as_ref() == "{" && c.as_ref() == "}")
+    )
+}
+
+/// Whether `latex` is a single character or command, which TeX takes as a whole argument
+fn single_token(latex: &str) -> bool {
+    let mut chars = latex.chars();
+    match (chars.next(), chars.next()) {
+        (Some(_), None) => true,
+        (Some('\\'), Some(c)) if !c.is_ascii_alphabetic() => chars.next().is_none(),
+        (Some('\\'), Some(_)) => chars.all(|c| c.is_ascii_alphabetic()),
+        _ => false,
+    }
+}
+
+/// The source of a symbol token, as is if the lexer reads it back, otherwise as a command
+///
+/// ASCII glyphs are preferred as is, other ones as their command.
+fn symbol(token: Token) -> Option<String> {
+    let glyph = token.glyph()?;
+    let as_is = lex(glyph) == Some(token);
+    if as_is && glyph.is_ascii() {
+        return Some(glyph.to_string());
+    }
+    match command(token) {
+        Some(command) => Some(format!("\\{command}")),
+        None => as_is.then(|| glyph.to_string()),
+    }
+}
+
+/// The command producing `token`, preferring names made of letters over `\=` and the like
+fn command(token: Token) -> Option<&'static str> {
+    let mut commands = SYMBOLS
+        .iter()
+        .filter(|symbol| symbol.token == token)
+        .map(|symbol| symbol.command);
+    let first = commands.next()?;
+    let alphabetic = |command: &&str| command.chars().all(|c| c.is_ascii_alphabetic());
+    if alphabetic(&first) {
+        return Some(first);
+    }
+    Some(commands.find(alphabetic).unwrap_or(first))
+}
+
+/// The token `text` is read as, if it is read as a single one
+fn lex(text: &str) -> Option<Token<'_>> {
+    let mut lexer = Lexer::new(text);
+    let token = lexer.next_token();
+    (lexer.next_token() == Token::EOF).then_some(token)
+}
@@ -1,5 +1,32 @@
-use crate::{models::Variant, token::Token};
-use core::str::Chars;
+use crate::{
+    models::Variant,
+    span::{Position, Span, Spanned},
+    token::Token,
+};
+use alloc::{boxed::Box, string::String, vec::Vec};
+use core::{
+    cell::{Cell, UnsafeCell},
+    str::Chars,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+/// Raw text of a macro argument or definition, read without tokenizing it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Group<'a> {
+    pub text: &'a str,
+    /// Whether the group was closed before the end of the input
+    pub closed: bool,
+}
+
+/// Progress through a run of superscript or subscript characters such as `²³`, which is
+/// lexed as `^{23}`, or `²` lexed as `^2`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Script {
+    /// The `^` or `_` was returned, the `{` comes next when the run has several characters
+    Open { superscript: bool, braced: bool },
+    /// Inside the group, until a character of another kind closes it
+    Group { superscript: bool },
+}
 
 /// Lexer
 #[derive(Debug, Clone)]
@@ -7,8 +34,16 @@ pub struct Lexer<'a> {
     pub(crate) input: &'a str,
     chars: Chars<'a>,
     pub(crate) index: usize,
+    /// Byte offset where the last token returned by [`Lexer::next_token`] starts
+    start: usize,
+    /// A byte offset at or before `start` along with its position, from where
+    /// [`Lexer::position`] counts lines and columns only when asked
+    checkpoint: Cell<(usize, Position)>,
     pub cur: char,
     pub peek: char,
+    /// Whether `%` comments are returned as [`Token::Comment`] rather than skipped
+    comments: bool,
+    script: Option<Script>,
 }
 
 impl<'a> Iterator for Lexer<'a> {
@@ -34,6 +69,19 @@ impl<'a> Lexer<'a> {
             peek: chars.next().unwrap_or('\u{0}'),
             chars,
             index: 0,
+            start: 0,
+            checkpoint: Cell::default(),
+            comments: false,
+            script: None,
+        }
+    }
+
+    /// Like [`Lexer::new`], returning comments as [`Token::Comment`] trivia instead of
+    /// skipping them
+    pub fn with_comments(input: &'a str) -> Self {
+        Lexer {
+            comments: true,
+            ..Lexer::new(input)
         }
     }
 
@@ -46,6 +94,22 @@ impl<'a> Lexer<'a> {
         c
     }
 
+    /// Line and columns where the last token returned by [`Lexer::next_token`] starts
+    pub fn position(&self) -> Position {
+        let start = self.start.min(self.input.len());
+        let (offset, position) = self.checkpoint.get();
+        let position = position.advance(&self.input.as_bytes()[offset..start]);
+        self.checkpoint.set((start, position));
+        position
+    }
+
+    /// Line and columns where the last token returned by [`Lexer::next_token`] ends
+    pub fn end_position(&self) -> Position {
+        let start = self.start.min(self.input.len());
+        let end = self.index.min(self.input.len());
+        self.position().advance(&self.input.as_bytes()[start..end])
+    }
+
     #[inline]
     pub(crate) fn grab_slice(&self, offset: usize) -> &'a str {
         if offset > self.index {
@@ -60,7 +124,7 @@ impl<'a> Lexer<'a> {
     }
 
     /// 空白文字をスキップする.
-    fn skip_whitespace(&mut self) {
+    pub(crate) fn skip_whitespace(&mut self) {
         while {
             let cur = self.cur;
             cur == ' ' || cur == '\t' || cur == '\n' || cur == '\r'
@@ -69,14 +133,93 @@ impl<'a> Lexer<'a> {
         }
     }
 
+    /// Reads a `%` comment through the end of its line, returns its text after the `%`
+    fn read_comment(&mut self) -> &'a str {
+        self.read_char(); // skip '%'
+        let start = self.index;
+        while self.cur != '\n' && !self.is_at_end() {
+            self.read_char();
+        }
+        &self.input[start.min(self.input.len())..self.index.min(self.input.len())]
+    }
+
     fn read_command(&mut self) -> Token<'a> {
+        Token::from_command(self.read_command_name())
+    }
+
+    /// Reads a control sequence, a `\` followed by either letters or a single other character
+    fn read_command_name(&mut self) -> &'a str {
         self.read_char(); // skip '\'
         let start = self.index;
+        if self.read_char().is_ascii_alphabetic() {
+            while self.cur.is_ascii_alphabetic() {
+                self.read_char();
+            }
+        }
+        &self.input[start.min(self.input.len())..self.index.min(self.input.len())]
+    }
+
+    pub(crate) fn is_at_end(&self) -> bool {
+        self.index >= self.input.len()
+    }
+
+    /// Reads the raw text between `open` and the matching `close` if the next character is `open`,
+    /// skipping over nested `{}` groups and escaped characters
+    pub(crate) fn read_delimited(&mut self, open: char, close: char) -> Option<Group<'a>> {
+        self.skip_whitespace();
+        if self.cur != open || self.is_at_end() {
+            return None;
+        }
         self.read_char();
-        while self.cur.is_ascii_alphabetic() {
+        let start = self.index;
+        let mut depth = 0;
+        loop {
+            match self.cur {
+                _ if self.is_at_end() => {
+                    return Some(Group {
+                        text: &self.input[start.min(self.input.len())..],
+                        closed: false,
+                    });
+                }
+                c if c == close && depth == 0 => {
+                    let text = &self.input[start..self.index];
+                    self.read_char();
+                    return Some(Group { text, closed: true });
+                }
+                '{' => depth += 1,
+                '}' if depth > 0 => depth -= 1,
+                '%' => {
+                    // Braces in a comment do not count, the comment stays in the text
+                    self.read_comment();
+                    continue;
+                }
+                '\\' => {
+                    self.read_char();
+                }
+                _ => {}
+            }
             self.read_char();
         }
-        Token::from_command(&self.input[start..self.index])
+    }
+
+    /// Reads a raw macro argument: a `{}` group, a control sequence or a single character
+    pub(crate) fn read_argument(&mut self) -> Option<Group<'a>> {
+        if let Some(group) = self.read_delimited('{', '}') {
+            return Some(group);
+        }
+        if self.is_at_end() {
+            return None;
+        }
+        let start = self.index;
+        if self.cur == '\\' {
+            self.read_command_name();
+        } else {
+            self.read_char();
+        }
+        Some(Group {
+            text: &self.input[start..self.index.min(self.input.len())],
+            closed: true,
+        })
     }
 
     fn read_number(&mut self) -> Token<'a> {
@@ -84,7 +227,7 @@ impl<'a> Lexer<'a> {
         let mut has_period = false;
         loop {
             let cur = self.cur;
-            if !cur.is_ascii_digit() && !(cur == '.' && !has_period) {
+            if !(cur.is_ascii_digit() || cur == '.' && !has_period) {
                 break;
             }
             if cur == '.' {
@@ -95,8 +238,24 @@ impl<'a> Lexer<'a> {
         Token::Number(&self.input[start..self.index])
     }
 
+    /// Returns the next token along with the span of input it was read from
+    pub fn next_spanned(&mut self) -> Spanned<Token<'a>> {
+        let token = self.next_token();
+        // Reading past the end of the input still advances the index
+        let len = self.input.len();
+        Spanned::new(token, Span::new(self.start.min(len), self.index.min(len)))
+    }
+
     pub fn next_token(&mut self) -> Token<'a> {
+        if let Some(script) = self.script {
+            return self.next_script(script);
+        }
         self.skip_whitespace();
+        while self.cur == '%' && !self.comments {
+            self.read_comment();
+            self.skip_whitespace();
+        }
+        self.start = self.index;
         let token = match self.cur {
             '=' => Token::Operator("="),
             ';' => Token::Operator(";"),
@@ -128,6 +287,11 @@ impl<'a> Lexer<'a> {
             '_' => Token::Underscore,
             '^' => Token::Circumflex,
             '&' => Token::Ampersand,
+            '#' if matches!(self.peek, '1'..='9') => {
+                self.read_char();
+                Token::Parameter(self.cur as u8 - b'0')
+            }
+            '%' => return Token::Comment(self.read_comment()),
             '\u{0}' => Token::EOF,
             ':' => {
                 if self.peek == '=' {
@@ -140,6 +304,24 @@ impl<'a> Lexer<'a> {
             '\\' => {
                 return self.read_command();
             }
+            c if script(c).is_some() => {
+                // Only marks the start of the run, its characters are read afterwards
+                let superscript = script(c).is_some_and(|(superscript, _)| superscript);
+                let braced = script(self.peek).is_some_and(|(kind, _)| kind == superscript);
+                self.script = Some(Script::Open {
+                    superscript,
+                    braced,
+                });
+                return if superscript {
+                    Token::Circumflex
+                } else {
+                    Token::Underscore
+                };
+            }
+            c if !c.is_ascii() => {
+                let symbol = &self.input[self.index..self.index + c.len_utf8()];
+                Token::from_symbol(symbol).unwrap_or(Token::Letter(symbol, Variant::Normal))
+            }
             c => {
                 if c.is_ascii_digit() {
                     return self.read_number();
@@ -158,4 +340,191 @@ impl<'a> Lexer<'a> {
         self.read_char();
         token
     }
+
+    /// Reads a run of superscript or subscript digits as one number
+    fn read_script_number(&mut self, superscript: bool) -> Token<'a> {
+        let mut digits = String::new();
+        let mut single = "";
+        while let Some((kind, Token::Number(digit))) = script(self.cur) {
+            if kind != superscript {
+                break;
+            }
+            digits.push_str(digit);
+            single = digit;
+            self.read_char();
+        }
+        match digits.len() {
+            1 => Token::Number(single),
+            _ => Token::Number(intern(digits)),
+        }
+    }
+
+    /// Returns the next token of a run of superscript or subscript characters
+    fn next_script(&mut self, state: Script) -> Token<'a> {
+        self.start = self.index;
+        match state {
+            Script::Open {
+                superscript,
+                braced: true,
+            } => {
+                self.script = Some(Script::Group { superscript });
+                Token::LSeperator("{")
+            }
+            Script::Open { braced: false, .. } => {
+                self.script = None;
+                let (_, token) = script(self.cur).expect("a script character comes next");
+                self.read_char();
+                token
+            }
+            Script::Group { superscript } => match script(self.cur) {
+                Some((kind, Token::Number(_))) if kind == superscript => {
+                    self.read_script_number(superscript)
+                }
+                Some((kind, token)) if kind == superscript => {
+                    self.read_char();
+                    token
+                }
+                _ => {
+                    self.script = None;
+                    Token::RSeperator("}")
+                }
+            },
+        }
+    }
 }
+
+/// The token a superscript or subscript character stands for, along with whether it is a
+/// superscript
+fn script(c: char) -> Option<(bool, Token<'static>)> {
+    if c.is_ascii() {
+        return None;
+    }
+    SCRIPTS
+        .iter()
+        .find(|(script, _, _)| *script == c)
+        .map(|(_, superscript, token)| (*superscript, *token))
+}
+
+/// Numbers of several superscript or subscript digits, which have no text in the input to
+/// borrow, each leaked once and shared by every run of the same digits
+static NUMBERS: Numbers = Numbers {
+    locked: AtomicBool::new(false),
+    numbers: UnsafeCell::new(Vec::new()),
+};
+
+struct Numbers {
+    locked: AtomicBool,
+    /// Sorted, only accessed while `locked` is held
+    numbers: UnsafeCell<Vec<&'static str>>,
+}
+
+// SAFETY: `numbers` is only accessed by `intern` while holding `locked`
+unsafe impl Sync for Numbers {}
+
+/// Releases [`NUMBERS`] when dropped
+struct NumbersLock;
+
+impl Drop for NumbersLock {
+    fn drop(&mut self) {
+        NUMBERS.locked.store(false, Ordering::Release);
+    }
+}
+
+/// Returns the shared text of the number `digits`
+fn intern(digits: String) -> &'static str {
+    while NUMBERS
+        .locked
+        .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
+        .is_err()
+    {
+        core::hint::spin_loop();
+    }
+    let _lock = NumbersLock;
+    // SAFETY: the lock is held until `_lock` is dropped
+    let numbers = unsafe { &mut *NUMBERS.numbers.get() };
+    match numbers.binary_search(&digits.as_str()) {
+        Ok(index) => numbers[index],
+        Err(index) => {
+            let number: &'static str = Box::leak(digits.into_boxed_str());
+            numbers.insert(index, number);
+            number
+        }
+    }
+}
+
+/// Superscript and subscript characters, whether they are superscripts and the token they
+/// stand for
+const SCRIPTS: &[(char, bool, Token<'static>)] = &[
+    ('⁰', true, Token::Number("0")),
+    ('¹', true, Token::Number("1")),
+    ('²', true, Token::Number("2")),
+    ('³', true, Token::Number("3")),
+    ('⁴', true, Token::Number("4")),
+    ('⁵', true, Token::Number("5")),
+    ('⁶', true, Token::Number("6")),
+    ('⁷', true, Token::Number("7")),
+    ('⁸', true, Token::Number("8")),
+    ('⁹', true, Token::Number("9")),
+    ('⁺', true, Token::Operator("+")),
+    ('⁻', true, Token::Operator("-")),
+    ('⁼', true, Token::Operator("=")),
+    ('⁽', true, Token::LSeperator("(")),
+    ('⁾', true, Token::RSeperator(")")),
+    ('ᵃ', true, Token::Letter("a", Variant::Italic)),
+    ('ᵇ', true, Token::Letter("b", Variant::Italic)),
+    ('ᶜ', true, Token::Letter("c", Variant::Italic)),
+    ('ᵈ', true, Token::Letter("d", Variant::Italic)),
+    ('ᵉ', true, Token::Letter("e", Variant::Italic)),
+    ('ᶠ', true, Token::Letter("f", Variant::Italic)),
+    ('ᵍ', true, Token::Letter("g", Variant::Italic)),
+    ('ʰ', true, Token::Letter("h", Variant::Italic)),
+    ('ⁱ', true, Token::Letter("i", Variant::Italic)),
+    ('ʲ', true, Token::Letter("j", Variant::Italic)),
+    ('ᵏ', true, Token::Letter("k", Variant::Italic)),
+    ('ˡ', true, Token::Letter("l", Variant::Italic)),
+    ('ᵐ', true, Token::Letter("m", Variant::Italic)),
+    ('ⁿ', true, Token::Letter("n", Variant::Italic)),
+    ('ᵒ', true, Token::Letter("o", Variant::Italic)),
+    ('ᵖ', true, Token::Letter("p", Variant::Italic)),
+    ('ʳ', true, Token::Letter("r", Variant::Italic)),
+    ('ˢ', true, Token::Letter("s", Variant::Italic)),
+    ('ᵗ', true, Token::Letter("t", Variant::Italic)),
+    ('ᵘ', true, Token::Letter("u", Variant::Italic)),
+    ('ᵛ', true, Token::Letter("v", Variant::Italic)),
+    ('ʷ', true, Token::Letter("w", Variant::Italic)),
+    ('ˣ', true, Token::Letter("x", Variant::Italic)),
+    ('ʸ', true, Token::Letter("y", Variant::Italic)),
+    ('ᶻ', true, Token::Letter("z", Variant::Italic)),
+    ('₀', false, Token::Number("0")),
+    ('₁', false, Token::Number("1")),
+    ('₂', false, Token::Number("2")),
+    ('₃', false, Token::Number("3")),
+    ('₄', false, Token::Number("4")),
+    ('₅', false, Token::Number("5")),
+    ('₆', false, Token::Number("6")),
+    ('₇', false, Token::Number("7")),
+    ('₈', false, Token::Number("8")),
+    ('₉', false, Token::Number("9")),
+    ('₊', false, Token::Operator("+")),
+    ('₋', false, Token::Operator("-")),
+    ('₌', false, Token::Operator("=")),
+    ('₍', false, Token::LSeperator("(")),
+    ('₎', false, Token::RSeperator(")")),
+    ('ₐ', false, Token::Letter("a", Variant::Italic)),
+    ('ₑ', false, Token::Letter("e", Variant::Italic)),
+    ('ₕ', false, Token::Letter("h", Variant::Italic)),
+    ('ᵢ', false, Token::Letter("i", Variant::Italic)),
+    ('ⱼ', false, Token::Letter("j", Variant::Italic)),
+    ('ₖ', false, Token::Letter("k", Variant::Italic)),
+    ('ₗ', false, Token::Letter("l", Variant::Italic)),
+    ('ₘ', false, Token::Letter("m", Variant::Italic)),
+    ('ₙ', false, Token::Letter("n", Variant::Italic)),
+    ('ₒ', false, Token::Letter("o", Variant::Italic)),
+    ('ₚ', false, Token::Letter("p", Variant::Italic)),
+    ('ᵣ', false, Token::Letter("r", Variant::Italic)),
+    ('ₛ', false, Token::Letter("s", Variant::Italic)),
+    ('ₜ', false, Token::Letter("t", Variant::Italic)),
+    ('ᵤ', false, Token::Letter("u", Variant::Italic)),
+    ('ᵥ', false, Token::Letter("v", Variant::Italic)),
+    ('ₓ', false, Token::Letter("x", Variant::Italic)),
+];
@@ -2,24 +2,48 @@
 extern crate alloc;
 use alloc::vec::Vec;
 
+mod ast;
+mod error;
+mod latex;
 mod lexer;
+mod macros;
 mod models;
+mod normalize;
 mod parser;
+mod preamble;
+mod span;
+mod symbols;
 mod token;
+mod visit;
 
+pub use ast::{Ast, AstNode, Children, NodeId, NodeRef};
+pub use error::{Diagnostic, ParseError, ParseErrorKind, Recovery};
+pub use latex::to_latex;
 pub use lexer::Lexer;
-pub use models::{Accent, ColumnAlign, DisplayStyle, IntoTexNodes, LineThickness, Node, Variant};
+pub use macros::MacroTable;
+pub use models::{
+    Accent, AtomClass, ColumnAlign, Descendants, Dimension, DisplayStyle, Grid, HLine,
+    IntoTexNodes, LineThickness, MultiColumn, Node, NodeBuf, NodeOf, PostOrder, Space, Unit,
+    Variant,
+};
+pub use normalize::normalize;
 pub use parser::Parser;
-pub use token::Token;
+pub use preamble::{Package, Preamble};
+pub use span::{Position, Span, SpanTable, Spanned};
+pub use symbols::{Symbol, SymbolClass, SYMBOLS};
+pub use token::{Token, TokenBuf, TokenOf};
+pub use visit::{Fold, Visit, VisitMut};
 
 #[cfg(test)]
 mod tests;
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(tag = "type", content = "value"))]
 pub enum TexNode<'a> {
     Text(&'a str),
-    Inline(Node<'a>),
-    Block(Node<'a>),
+    Inline(#[cfg_attr(feature = "serde", serde(borrow))] Node<'a>),
+    Block(#[cfg_attr(feature = "serde", serde(borrow))] Node<'a>),
 }
 
 // TODO Make this a trait that returns an iterator
@@ -0,0 +1,475 @@
+use crate::{
+    error::{Diagnostic, ParseError, ParseErrorKind, Recovery},
+    lexer::{Group, Lexer},
+    span::{Span, Spanned},
+    token::Token,
+};
+use alloc::{
+    collections::BTreeMap,
+    string::{String, ToString},
+    vec::Vec,
+};
+
+/// How deeply macros may expand into each other, which stops recursive definitions
+const MAX_DEPTH: usize = 64;
+
+/// A macro defined by `\newcommand`, `\renewcommand`, `\def` or `\DeclareMathOperator`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Macro<'a> {
+    /// Number of parameters, `#1` to `#9`
+    pub params: u8,
+    /// Default value of the first parameter, which makes it optional
+    pub default: Option<&'a str>,
+    pub body: &'a str,
+    pub expansion: Expansion,
+}
+
+/// What a macro is replaced with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Expansion {
+    /// The tokens of its body, with its arguments in place of the parameters
+    Body,
+    /// A single operator named by its body, placing scripts under and over it with `limits`
+    Operator { limits: bool },
+}
+
+/// Macros to expand in every input parsed with them, see [`Parser::with_macros`]
+///
+/// The table acts like a preamble shared by the inputs: they can use its macros and
+/// `\renewcommand` them, and its macros take priority over the built in commands.
+///
+/// [`Parser::with_macros`]: crate::Parser::with_macros
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MacroTable {
+    macros: BTreeMap<String, Definition>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Definition {
+    params: u8,
+    default: Option<String>,
+    body: String,
+    expansion: Expansion,
+}
+
+impl MacroTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Defines the macro `name`, with or without its leading `\`, replacing `#1` to `#9` in
+    /// `body` with its `params` arguments
+    ///
+    /// # Panics
+    ///
+    /// Panics if `params` is greater than 9
+    pub fn define(&mut self, name: &str, params: u8, body: &str) -> &mut Self {
+        self.insert(name, Macro::new(params, None, body))
+    }
+
+    /// Like [`MacroTable::define`], making the first parameter optional with the given default,
+    /// as `\newcommand{\name}[params][default]{body}` does
+    ///
+    /// # Panics
+    ///
+    /// Panics if `params` is 0 or greater than 9
+    pub fn define_optional(
+        &mut self,
+        name: &str,
+        params: u8,
+        default: &str,
+        body: &str,
+    ) -> &mut Self {
+        assert!(
+            params > 0,
+            "a macro with an optional argument needs a parameter"
+        );
+        self.insert(name, Macro::new(params, Some(default), body))
+    }
+
+    /// Defines the operator `name` set in upright `text`, as `\DeclareMathOperator` does
+    ///
+    /// With `limits`, subscripts and superscripts of the operator go under and over it as for
+    /// `\DeclareMathOperator*`.
+    pub fn define_operator(&mut self, name: &str, text: &str, limits: bool) -> &mut Self {
+        let mac = Macro {
+            expansion: Expansion::Operator { limits },
+            ..Macro::new(0, None, text)
+        };
+        self.insert(name, mac)
+    }
+
+    pub(crate) fn insert(&mut self, name: &str, mac: Macro<'_>) -> &mut Self {
+        assert!(mac.params <= 9, "macros have at most 9 parameters");
+        let definition = Definition {
+            params: mac.params,
+            default: mac.default.map(ToString::to_string),
+            body: mac.body.to_string(),
+            expansion: mac.expansion,
+        };
+        self.macros
+            .insert(command_name(name).to_string(), definition);
+        self
+    }
+
+    /// Removes the macro `name`, returns whether it was defined
+    pub fn remove(&mut self, name: &str) -> bool {
+        self.macros.remove(command_name(name)).is_some()
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.macros.contains_key(command_name(name))
+    }
+
+    /// Names of the macros, without their leading `\`
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.macros.keys().map(String::as_str)
+    }
+
+    pub fn len(&self) -> usize {
+        self.macros.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.macros.is_empty()
+    }
+
+    pub(crate) fn get(&self, name: &str) -> Option<(&str, Macro<'_>)> {
+        let (name, definition) = self.macros.get_key_value(name)?;
+        let mac = Macro {
+            params: definition.params,
+            default: definition.default.as_deref(),
+            body: &definition.body,
+            expansion: definition.expansion,
+        };
+        Some((name, mac))
+    }
+}
+
+impl<'a> Macro<'a> {
+    fn new(params: u8, default: Option<&'a str>, body: &'a str) -> Self {
+        Self {
+            params,
+            default,
+            body,
+            expansion: Expansion::Body,
+        }
+    }
+}
+
+fn command_name(name: &str) -> &str {
+    name.strip_prefix('\\').unwrap_or(name)
+}
+
+/// A macro argument, with the frame its own parameters refer to
+#[derive(Debug, Clone, Copy)]
+struct Arg<'a> {
+    text: &'a str,
+    scope: usize,
+}
+
+#[derive(Debug, Clone)]
+struct Frame<'a> {
+    lexer: Lexer<'a>,
+    /// Span of the outermost macro call the frame expands, every token of the frame gets it
+    call: Option<Span>,
+    args: Vec<Arg<'a>>,
+    /// Index of the frame holding the arguments parameters in this frame refer to
+    scope: usize,
+}
+
+impl Frame<'_> {
+    fn span(&self, local: Span) -> Span {
+        self.call.unwrap_or(local)
+    }
+}
+
+/// The text a token was read from, which is a macro body or argument rather than the input
+/// for tokens produced by macro expansion
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Source<'a> {
+    pub text: &'a str,
+    /// Location of the token in `text`
+    pub span: Span,
+}
+
+/// Reads tokens from the input, replacing macros with their expansion and consuming
+/// their definitions
+#[derive(Debug, Clone)]
+pub(crate) struct Expander<'a> {
+    frames: Vec<Frame<'a>>,
+    /// Macros defined in the input
+    macros: BTreeMap<&'a str, Macro<'a>>,
+    table: Option<&'a MacroTable>,
+    /// Errors found since the last call to [`Expander::take_errors`]
+    errors: Vec<Diagnostic<'a>>,
+}
+
+impl<'a> Expander<'a> {
+    pub fn new(input: &'a str, table: Option<&'a MacroTable>) -> Self {
+        Self {
+            frames: alloc::vec![Frame {
+                lexer: Lexer::new(input),
+                call: None,
+                args: Vec::new(),
+                scope: 0,
+            }],
+            macros: BTreeMap::new(),
+            table,
+            errors: Vec::new(),
+        }
+    }
+
+    pub fn take_errors(&mut self) -> Vec<Diagnostic<'a>> {
+        core::mem::take(&mut self.errors)
+    }
+
+    /// Looks up a macro, the ones defined in the input shadow the ones of the table
+    fn lookup(&self, name: &str) -> Option<(&'a str, Macro<'a>)> {
+        match self.macros.get_key_value(name) {
+            Some((name, mac)) => Some((name, *mac)),
+            None => self.table?.get(name),
+        }
+    }
+
+    fn top(&mut self) -> &mut Frame<'a> {
+        let last = self.frames.len() - 1;
+        &mut self.frames[last]
+    }
+
+    fn error(
+        &mut self,
+        kind: ParseErrorKind<'a>,
+        span: Span,
+        found: Token<'a>,
+        recovery: Recovery,
+    ) {
+        let error = ParseError {
+            kind,
+            span,
+            expected: None,
+            found,
+        };
+        self.errors.push(Diagnostic { error, recovery });
+    }
+
+    /// Span in the input of what the top frame read since the local offset `start`
+    fn span_from(&self, start: usize) -> Span {
+        let frame = &self.frames[self.frames.len() - 1];
+        let end = frame.lexer.index.min(frame.lexer.input.len());
+        frame.span(Span::new(start, end))
+    }
+
+    /// Returns the next token after expanding macros
+    pub fn next_spanned(&mut self) -> (Spanned<Token<'a>>, Source<'a>) {
+        loop {
+            let frame = self.top();
+            let local = frame.lexer.next_spanned();
+            let text = frame.lexer.input;
+            let span = frame.span(local.span);
+            let source = Source {
+                text,
+                span: local.span,
+            };
+            match local.value {
+                Token::EOF if self.frames.len() > 1 => {
+                    self.frames.pop();
+                    continue;
+                }
+                Token::Parameter(n) => {
+                    let scope = self.frames[self.frames.len() - 1].scope;
+                    if let Some(&arg) = self.frames[scope].args.get(usize::from(n) - 1) {
+                        self.push(arg.text, span, Vec::new(), arg.scope);
+                        continue;
+                    }
+                }
+                Token::NewCommand
+                | Token::RenewCommand
+                | Token::Def
+                | Token::DeclareMathOperator => {
+                    self.define(local.value, local.span.start);
+                    continue;
+                }
+                _ => {}
+            }
+            let command = text[local.span.start..local.span.end].strip_prefix('\\');
+            if let Some((name, mac)) = command.and_then(|name| self.lookup(name)) {
+                let token = match mac.expansion {
+                    Expansion::Body => {
+                        self.expand(mac, Token::Command(name), local.span.start);
+                        continue;
+                    }
+                    Expansion::Operator { limits: true } => Token::Lim(mac.body),
+                    Expansion::Operator { limits: false } => Token::Function(mac.body),
+                };
+                return (Spanned::new(token, span), source);
+            }
+            return (Spanned::new(local.value, span), source);
+        }
+    }
+
+    /// Reads a digit right after the last token, for commands such as `\frac12`
+    pub fn next_digit(&mut self) -> Option<(Spanned<Token<'a>>, Source<'a>)> {
+        while self.frames.len() > 1 && self.top().lexer.is_at_end() {
+            self.frames.pop();
+        }
+        let frame = self.top();
+        if !frame.lexer.cur.is_ascii_digit() {
+            return None;
+        }
+        let start = frame.lexer.index;
+        let c = frame.lexer.read_char();
+        let local = Span::new(start, frame.lexer.index);
+        let source = Source {
+            text: frame.lexer.input,
+            span: local,
+        };
+        let token = Token::Number(frame.lexer.grab_slice(c.len_utf8()));
+        Some((Spanned::new(token, frame.span(local)), source))
+    }
+
+    fn push(&mut self, text: &'a str, call: Span, args: Vec<Arg<'a>>, scope: usize) {
+        self.frames.push(Frame {
+            lexer: Lexer::new(text),
+            call: Some(call),
+            args,
+            scope,
+        });
+    }
+
+    /// Reads a group of the top frame as an argument
+    fn arg(&mut self, group: Group<'a>) -> Arg<'a> {
+        Arg {
+            text: group.text,
+            scope: self.top().scope,
+        }
+    }
+
+    /// Reads the arguments of the macro `mac` called by `token` and pushes its body
+    fn expand(&mut self, mac: Macro<'a>, token: Token<'a>, start: usize) {
+        if self.frames.len() >= MAX_DEPTH {
+            let span = self.span_from(start);
+            self.error(ParseErrorKind::MacroDepth, span, token, Recovery::Skipped);
+            // Give up on expanding and carry on with the rest of the input
+            self.frames.truncate(1);
+            return;
+        }
+        let mut args = Vec::with_capacity(usize::from(mac.params));
+        let mut default = None;
+        if let Some(text) = mac.default {
+            match self.top().lexer.read_delimited('[', ']') {
+                Some(group) => args.push(self.arg(group)),
+                None => default = Some(text),
+            }
+        }
+        while args.len() + usize::from(default.is_some()) < usize::from(mac.params) {
+            let group = self.top().lexer.read_argument();
+            let error = match group {
+                Some(group) if group.closed => None,
+                Some(_) => Some((ParseErrorKind::UnterminatedGroup, Recovery::ClosedGroup)),
+                None => Some((ParseErrorKind::MissingArgument, Recovery::Substituted)),
+            };
+            if let Some((kind, recovery)) = error {
+                let span = self.span_from(start);
+                self.error(kind, span, token, recovery);
+            }
+            let group = group.unwrap_or(Group {
+                text: "",
+                closed: true,
+            });
+            args.push(self.arg(group));
+        }
+
+        let span = self.span_from(start);
+        if let Some(text) = default {
+            let arg = Arg {
+                text,
+                scope: self.top().scope,
+            };
+            args.insert(0, arg);
+        }
+        let scope = self.frames.len();
+        self.push(mac.body, span, args, scope);
+    }
+
+    /// Reads the definition following `token` in the top frame and stores the macro
+    fn define(&mut self, token: Token<'a>, start: usize) {
+        let definition = read_definition(&mut self.top().lexer, token);
+        let span = self.span_from(start);
+        let (name, mac) = match definition {
+            Ok(definition) => definition,
+            Err(kind) => return self.error(kind, span, token, Recovery::Skipped),
+        };
+        let defined =
+            self.lookup(name).is_some() || !matches!(Token::from_command(name), Token::Command(_));
+        let redefines = matches!(token, Token::NewCommand | Token::DeclareMathOperator);
+        if redefines && defined {
+            let kind = ParseErrorKind::AlreadyDefined(name);
+            return self.error(kind, span, token, Recovery::Skipped);
+        }
+        self.macros.insert(name, mac);
+    }
+}
+
+/// Reads the name, parameters and body of a macro following a `\newcommand`,
+/// `\renewcommand`, `\def` or `\DeclareMathOperator` token
+pub(crate) fn read_definition<'a>(
+    lexer: &mut Lexer<'a>,
+    token: Token<'a>,
+) -> Result<(&'a str, Macro<'a>), ParseErrorKind<'a>> {
+    let mut expansion = Expansion::Body;
+    if token == Token::DeclareMathOperator {
+        lexer.skip_whitespace();
+        let limits = lexer.cur == '*';
+        if limits {
+            lexer.read_char();
+        }
+        expansion = Expansion::Operator { limits };
+    }
+    let name = lexer
+        .read_argument()
+        .and_then(|group| group.text.trim().strip_prefix('\\'))
+        .filter(|name| !name.is_empty());
+    let mut params = 0;
+    let mut default = None;
+    let mut valid = true;
+    if token == Token::Def {
+        // Parameter text such as `#1#2`
+        loop {
+            lexer.skip_whitespace();
+            if lexer.cur != '#' || !lexer.peek.is_ascii_digit() {
+                break;
+            }
+            lexer.read_char();
+            let digit = lexer.read_char() as u8 - b'0';
+            valid &= digit == params + 1;
+            params = digit;
+        }
+    } else if token != Token::DeclareMathOperator {
+        if let Some(group) = lexer.read_delimited('[', ']') {
+            match group.text.trim().parse() {
+                Ok(n @ 0..=9) => params = n,
+                _ => valid = false,
+            }
+        }
+        default = lexer.read_delimited('[', ']').map(|group| group.text);
+    }
+    let body = lexer.read_argument();
+
+    let (Some(name), Some(body)) = (name, body) else {
+        return Err(ParseErrorKind::InvalidDefinition);
+    };
+    if !valid || (default.is_some() && params == 0) {
+        return Err(ParseErrorKind::InvalidDefinition);
+    }
+    if !body.closed {
+        return Err(ParseErrorKind::UnterminatedGroup);
+    }
+    let mac = Macro {
+        params,
+        default,
+        body: body.text,
+        expansion,
+    };
+    Ok((name, mac))
+}
@@ -1,9 +1,9 @@
-use crate::token::Token;
-use alloc::boxed::Box;
-use alloc::vec::Vec;
+use crate::{symbols::glyph_class, token::TokenOf};
+use alloc::{boxed::Box, format, string::String, vec::Vec};
 
 /// mi mathvariant attribute
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Variant {
     Normal,
     Italic,
@@ -48,7 +48,8 @@ impl core::fmt::Display for Variant {
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum DisplayStyle {
     Block,
     Inline,
@@ -63,7 +64,8 @@ impl DisplayStyle {
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Accent {
     True,
     False,
@@ -78,7 +80,9 @@ impl core::fmt::Display for Accent {
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(tag = "type", content = "value"))]
 pub enum LineThickness {
     Thin,
     Medium,
@@ -97,7 +101,8 @@ impl core::fmt::Display for LineThickness {
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum ColumnAlign {
     Center,
     Left,
@@ -114,7 +119,342 @@ impl core::fmt::Display for ColumnAlign {
     }
 }
 
+/// A unit of a [`Dimension`], the ones TeX knows
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Unit {
+    Pt,
+    Pc,
+    In,
+    Bp,
+    Cm,
+    Mm,
+    Dd,
+    Cc,
+    Sp,
+    Em,
+    Ex,
+    Mu,
+}
+
+impl Unit {
+    pub fn to_str(self) -> &'static str {
+        match self {
+            Unit::Pt => "pt",
+            Unit::Pc => "pc",
+            Unit::In => "in",
+            Unit::Bp => "bp",
+            Unit::Cm => "cm",
+            Unit::Mm => "mm",
+            Unit::Dd => "dd",
+            Unit::Cc => "cc",
+            Unit::Sp => "sp",
+            Unit::Em => "em",
+            Unit::Ex => "ex",
+            Unit::Mu => "mu",
+        }
+    }
+
+    fn from_str(unit: &str) -> Option<Unit> {
+        let unit = match unit {
+            "pt" => Unit::Pt,
+            "pc" => Unit::Pc,
+            "in" => Unit::In,
+            "bp" => Unit::Bp,
+            "cm" => Unit::Cm,
+            "mm" => Unit::Mm,
+            "dd" => Unit::Dd,
+            "cc" => Unit::Cc,
+            "sp" => Unit::Sp,
+            "em" => Unit::Em,
+            "ex" => Unit::Ex,
+            "mu" => Unit::Mu,
+            _ => return None,
+        };
+        Some(unit)
+    }
+}
+
+/// A length as written in the source, such as the `2pt` of `\\[2pt]`
+///
+/// The value is kept in thousandths of its unit, so that lengths compare exactly.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Dimension {
+    /// Thousandths of the unit
+    pub value: i32,
+    pub unit: Unit,
+}
+
+impl Dimension {
+    /// Reads a length such as `2pt` or `-0.5em`, digits past the third decimal are dropped
+    pub fn parse(text: &str) -> Option<Dimension> {
+        let text = text.trim();
+        let (negative, text) = match text.strip_prefix('-') {
+            Some(text) => (true, text),
+            None => (false, text.strip_prefix('+').unwrap_or(text)),
+        };
+        let split = text
+            .find(|c: char| !c.is_ascii_digit() && c != '.')
+            .unwrap_or(text.len());
+        let (number, unit) = text.split_at(split);
+        let unit = Unit::from_str(unit.trim_start())?;
+        let (whole, fraction) = number.split_once('.').unwrap_or((number, ""));
+        if whole.is_empty() && fraction.is_empty() || fraction.contains('.') {
+            return None;
+        }
+        let mut value: i32 = match whole {
+            "" => 0,
+            whole => whole.parse::<i32>().ok()?.checked_mul(1000)?,
+        };
+        for (digit, scale) in fraction.bytes().zip([100, 10, 1]) {
+            value = value.checked_add(i32::from(digit - b'0') * scale)?;
+        }
+        Some(Dimension {
+            value: if negative { -value } else { value },
+            unit,
+        })
+    }
+}
+
+impl core::fmt::Display for Dimension {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        if self.value < 0 {
+            write!(f, "-")?;
+        }
+        let value = self.value.unsigned_abs();
+        write!(f, "{}", value / 1000)?;
+        let fraction = value % 1000;
+        if fraction != 0 {
+            let digits = format!("{fraction:03}");
+            write!(f, ".{}", digits.trim_end_matches('0'))?;
+        }
+        write!(f, "{}", self.unit.to_str())
+    }
+}
+
+/// A horizontal rule of a [`Grid`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct HLine {
+    /// The row the rule is drawn above, the number of rows for one below the last row
+    pub row: usize,
+    /// The first and last columns the rule spans, counted from 0, `None` for the full width
+    pub columns: Option<(usize, usize)>,
+}
+
+/// A cell spanning several columns, written with `\multicolumn`
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MultiColumn {
+    /// The row of the cell
+    pub row: usize,
+    /// The index of the cell in its row, which is not its column after another multicolumn
+    pub cell: usize,
+    /// Number of columns the cell spans
+    pub columns: usize,
+    /// Alignment of the cell, in place of the one of its columns
+    pub align: ColumnAlign,
+    /// Width of a `p{..}` cell
+    pub width: Option<Dimension>,
+    /// Vertical rules, 0 for one before the cell and 1 for one after it
+    pub vlines: Vec<usize>,
+}
+
+/// The cells of an environment such as `matrix` or `array`, row by row, along with their layout
+///
+/// Cells are nodes in a [`Node::Matrix`], and [`NodeId`](crate::NodeId)s in an
+/// [`AstNode::Matrix`](crate::AstNode::Matrix).
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Grid<C> {
+    /// Cells of every row, an empty cell being an empty `Row`, rows may be shorter than others
+    pub rows: Vec<Vec<C>>,
+    /// Alignment of every column, as many as cells in the longest row for a matrix and as
+    /// many as the column specification of an `array` has
+    pub columns: Vec<ColumnAlign>,
+    /// Width of every column, set for the `p{..}` columns of an `array`
+    pub widths: Vec<Option<Dimension>>,
+    /// Space added below every row, as in `\\[2pt]`
+    pub row_spacing: Vec<Option<Dimension>>,
+    /// Horizontal rules, in the order they are drawn from the top
+    pub hlines: Vec<HLine>,
+    /// Vertical rules, each the index of the column it is drawn before, the number of columns
+    /// for one after the last column, repeated for a double rule
+    pub vlines: Vec<usize>,
+    /// The `@{..}` separators of an `array`, each with the index of the column it is put
+    /// before, in place of the space between the columns
+    pub separators: Vec<(usize, C)>,
+    /// Cells spanning several columns
+    pub multicolumns: Vec<MultiColumn>,
+}
+
+impl<C> Default for Grid<C> {
+    fn default() -> Self {
+        Grid {
+            rows: Vec::new(),
+            columns: Vec::new(),
+            widths: Vec::new(),
+            row_spacing: Vec::new(),
+            hlines: Vec::new(),
+            vlines: Vec::new(),
+            separators: Vec::new(),
+            multicolumns: Vec::new(),
+        }
+    }
+}
+
+impl<C> Grid<C> {
+    /// Iterates over the cells, row by row
+    pub fn cells(&self) -> impl Iterator<Item = &C> {
+        self.rows.iter().flatten()
+    }
+
+    /// The `index`th cell, counting row by row
+    pub fn cell(&self, mut index: usize) -> Option<&C> {
+        for row in &self.rows {
+            match row.get(index) {
+                Some(cell) => return Some(cell),
+                None => index -= row.len(),
+            }
+        }
+        None
+    }
+
+    /// Iterates over the separators and then the cells, the order they are written in
+    pub fn children(&self) -> impl Iterator<Item = &C> {
+        self.separators
+            .iter()
+            .map(|(_, separator)| separator)
+            .chain(self.cells())
+    }
+
+    /// Like [`Grid::children`], mutably
+    pub fn children_mut(&mut self) -> impl Iterator<Item = &mut C> {
+        self.separators
+            .iter_mut()
+            .map(|(_, separator)| separator)
+            .chain(self.rows.iter_mut().flatten())
+    }
+
+    /// The `index`th of [`Grid::children`]
+    pub fn child(&self, index: usize) -> Option<&C> {
+        match self.separators.get(index) {
+            Some((_, separator)) => Some(separator),
+            None => self.cell(index - self.separators.len()),
+        }
+    }
+
+    /// The multicolumn at the `cell`th cell of `row`, if that cell is one
+    pub fn multicolumn(&self, row: usize, cell: usize) -> Option<&MultiColumn> {
+        self.multicolumns
+            .iter()
+            .find(|multicolumn| (multicolumn.row, multicolumn.cell) == (row, cell))
+    }
+
+    /// Converts the separators and then every cell with `f`, keeping the layout
+    pub fn map<D>(self, mut f: impl FnMut(C) -> D) -> Grid<D> {
+        Grid {
+            separators: self
+                .separators
+                .into_iter()
+                .map(|(column, separator)| (column, f(separator)))
+                .collect(),
+            rows: self
+                .rows
+                .into_iter()
+                .map(|row| row.into_iter().map(&mut f).collect())
+                .collect(),
+            columns: self.columns,
+            widths: self.widths,
+            row_spacing: self.row_spacing,
+            hlines: self.hlines,
+            vlines: self.vlines,
+            multicolumns: self.multicolumns,
+        }
+    }
+
+    /// Sets every column to `align`, adding columns up to the longest row
+    pub(crate) fn align(&mut self, align: impl Fn(usize) -> ColumnAlign) {
+        let count = self.rows.iter().map(Vec::len).max().unwrap_or(0);
+        self.columns = (0..count).map(align).collect();
+        self.widths = alloc::vec![None; count];
+    }
+}
+
+/// TeX's class of a math atom, which sets the spacing around it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum AtomClass {
+    /// An ordinary symbol, such as `x` or `\infty`
+    Ord,
+    /// A large operator, such as `\sum` or `\sin`
+    Op,
+    /// A binary operation, such as `+` or `\cup`
+    Bin,
+    /// A relation, such as `=` or `\to`
+    Rel,
+    /// An opening delimiter, such as `(`
+    Open,
+    /// A closing delimiter, such as `)`
+    Close,
+    /// A punctuation mark, such as `,`
+    Punct,
+    /// A delimited subformula, such as `\left( x \right)` or a fraction
+    Inner,
+}
+
+impl AtomClass {
+    pub fn to_str(self) -> &'static str {
+        match self {
+            AtomClass::Ord => "ord",
+            AtomClass::Op => "op",
+            AtomClass::Bin => "bin",
+            AtomClass::Rel => "rel",
+            AtomClass::Open => "open",
+            AtomClass::Close => "close",
+            AtomClass::Punct => "punct",
+            AtomClass::Inner => "inner",
+        }
+    }
+}
+
+impl core::fmt::Display for AtomClass {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{}", self.to_str())
+    }
+}
+
+/// A horizontal width in math units, 18 of which make an em
+///
+/// Widths are whole numbers of units so that they compare exactly, the spaces of LaTeX such
+/// as `\,` (3 units) and `\quad` (18 units) all being whole.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Space(i32);
+
+impl Space {
+    pub const fn from_mu(mu: i32) -> Self {
+        Space(mu)
+    }
+
+    pub const fn mu(self) -> i32 {
+        self.0
+    }
+
+    pub fn em(self) -> f32 {
+        self.0 as f32 / 18.
+    }
+}
+
+impl core::fmt::Display for Space {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{}em", self.em())
+    }
+}
+
 pub trait IntoTexNodes<'a> {
+    #[allow(clippy::wrong_self_convention)]
     fn into_nodes(&'a self) -> Vec<Node<'a>>;
 }
 
@@ -127,67 +467,77 @@ where
     }
 }
 
-type NodeBox<'a> = Box<Node<'a>>;
+type NodeBox<S> = Box<NodeOf<S>>;
 
 // What other nodes need variant?
-/// AST node
-#[derive(Debug, Clone, PartialEq)]
-pub enum Node<'a> {
-    Package(&'a str),
-    Title(&'a str),
-    Number(&'a str),
-    Operator(&'a str),
-    Text(&'a str, Variant),
-    Letter(&'a str, Variant),
-    Function(&'a str, Option<NodeBox<'a>>),
-    Space(f32),
-    Subscript(NodeBox<'a>, NodeBox<'a>),
-    Superscript(NodeBox<'a>, NodeBox<'a>),
+/// AST node, generic over the text it stores, see [`Node`] and [`NodeBuf`]
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(tag = "type", content = "value"))]
+pub enum NodeOf<S> {
+    Package(S),
+    Title(S),
+    Number(S),
+    Operator(S),
+    Text(S, Variant),
+    Letter(S, Variant),
+    Function(S, Option<NodeBox<S>>),
+    Space(Space),
+    Subscript(NodeBox<S>, NodeBox<S>),
+    Superscript(NodeBox<S>, NodeBox<S>),
     SubSup {
-        target: NodeBox<'a>,
-        sub: NodeBox<'a>,
-        sup: NodeBox<'a>,
+        target: NodeBox<S>,
+        sub: NodeBox<S>,
+        sup: NodeBox<S>,
     },
-    OverOp(&'a str, Accent, NodeBox<'a>),
-    UnderOp(&'a str, Accent, NodeBox<'a>),
+    OverOp(S, Accent, NodeBox<S>),
+    UnderOp(S, Accent, NodeBox<S>),
     Overset {
-        over: NodeBox<'a>,
-        target: NodeBox<'a>,
+        over: NodeBox<S>,
+        target: NodeBox<S>,
     },
     Underset {
-        under: NodeBox<'a>,
-        target: NodeBox<'a>,
+        under: NodeBox<S>,
+        target: NodeBox<S>,
     },
     UnderOver {
-        target: NodeBox<'a>,
-        under: NodeBox<'a>,
-        over: NodeBox<'a>,
+        target: NodeBox<S>,
+        under: NodeBox<S>,
+        over: NodeBox<S>,
     },
-    Sqrt(Option<NodeBox<'a>>, NodeBox<'a>),
-    Frac(NodeBox<'a>, NodeBox<'a>, LineThickness),
-    // Row(smallvec::SmallVec<[Node<'a>;N]>),
-    Row(Vec<Node<'a>>),
+    Sqrt(Option<NodeBox<S>>, NodeBox<S>),
+    Frac(NodeBox<S>, NodeBox<S>, LineThickness),
+    // Row(smallvec::SmallVec<[NodeOf<S>;N]>),
+    Row(Vec<NodeOf<S>>),
     Fenced {
-        open: NodeBox<'a>,
-        close: NodeBox<'a>,
-        content: NodeBox<'a>,
+        open: NodeBox<S>,
+        close: NodeBox<S>,
+        content: NodeBox<S>,
     },
-    StrechedOp(bool, &'a str),
-    OtherOperator(&'a str),
+    StrechedOp(bool, S),
+    OtherOperator(S),
     SizedParen {
-        size: &'a str,
-        paren: &'a str,
+        size: S,
+        paren: S,
     },
-    Matrix(NodeBox<'a>, ColumnAlign),
+    Matrix(Box<Grid<NodeOf<S>>>),
     Ampersand,
     NewLine,
-    Slashed(NodeBox<'a>),
-    Style(DisplayStyle, NodeBox<'a>),
-    Undefined(Token<'a>),
+    Slashed(NodeBox<S>),
+    Style(DisplayStyle, NodeBox<S>),
+    /// A subformula set to an atom class, as by `\mathbin{..}`
+    Atom(AtomClass, NodeBox<S>),
+    Undefined(TokenOf<S>),
 }
 
+/// AST node borrowing its text from the input
+pub type Node<'a> = NodeOf<&'a str>;
+
+/// AST node owning its text, which outlives the input, see [`Node::into_owned`]
+pub type NodeBuf = NodeOf<String>;
+
 impl<'a> Node<'a> {
-    pub fn arg(self) -> NodeBox<'a> {
+    pub fn arg(self) -> Box<Node<'a>> {
         match self {
             Node::Fenced {
                 open,
@@ -200,63 +550,340 @@ impl<'a> Node<'a> {
         }
     }
 
+    /// Copies the text of the tree, so that it no longer borrows the input
+    pub fn into_owned(self) -> NodeBuf {
+        self.map_text(String::from)
+    }
+}
+
+impl<S> NodeOf<S> {
+    /// Converts every text of the tree with `f`
+    pub fn map_text<T>(self, mut f: impl FnMut(S) -> T) -> NodeOf<T> {
+        self.map_text_with(&mut f)
+    }
+
+    fn map_text_with<T>(self, f: &mut impl FnMut(S) -> T) -> NodeOf<T> {
+        let mut map = |node: NodeBox<S>| Box::new(node.map_text_with(f));
+        match self {
+            NodeOf::Package(s) => NodeOf::Package(f(s)),
+            NodeOf::Title(s) => NodeOf::Title(f(s)),
+            NodeOf::Number(s) => NodeOf::Number(f(s)),
+            NodeOf::Operator(s) => NodeOf::Operator(f(s)),
+            NodeOf::Text(s, variant) => NodeOf::Text(f(s), variant),
+            NodeOf::Letter(s, variant) => NodeOf::Letter(f(s), variant),
+            NodeOf::Function(s, arg) => {
+                let s = f(s);
+                NodeOf::Function(s, arg.map(|arg| Box::new(arg.map_text_with(f))))
+            }
+            NodeOf::Space(space) => NodeOf::Space(space),
+            NodeOf::Subscript(target, script) => NodeOf::Subscript(map(target), map(script)),
+            NodeOf::Superscript(target, script) => NodeOf::Superscript(map(target), map(script)),
+            NodeOf::SubSup { target, sub, sup } => NodeOf::SubSup {
+                target: map(target),
+                sub: map(sub),
+                sup: map(sup),
+            },
+            NodeOf::OverOp(s, accent, target) => {
+                let s = f(s);
+                NodeOf::OverOp(s, accent, Box::new(target.map_text_with(f)))
+            }
+            NodeOf::UnderOp(s, accent, target) => {
+                let s = f(s);
+                NodeOf::UnderOp(s, accent, Box::new(target.map_text_with(f)))
+            }
+            NodeOf::Overset { over, target } => NodeOf::Overset {
+                over: map(over),
+                target: map(target),
+            },
+            NodeOf::Underset { under, target } => NodeOf::Underset {
+                under: map(under),
+                target: map(target),
+            },
+            NodeOf::UnderOver {
+                target,
+                under,
+                over,
+            } => NodeOf::UnderOver {
+                target: map(target),
+                under: map(under),
+                over: map(over),
+            },
+            NodeOf::Sqrt(degree, content) => NodeOf::Sqrt(degree.map(&mut map), map(content)),
+            NodeOf::Frac(numerator, denominator, thickness) => {
+                NodeOf::Frac(map(numerator), map(denominator), thickness)
+            }
+            NodeOf::Row(nodes) => NodeOf::Row(
+                nodes
+                    .into_iter()
+                    .map(|node| node.map_text_with(f))
+                    .collect(),
+            ),
+            NodeOf::Fenced {
+                open,
+                close,
+                content,
+            } => NodeOf::Fenced {
+                open: map(open),
+                close: map(close),
+                content: map(content),
+            },
+            NodeOf::StrechedOp(stretchy, s) => NodeOf::StrechedOp(stretchy, f(s)),
+            NodeOf::OtherOperator(s) => NodeOf::OtherOperator(f(s)),
+            NodeOf::SizedParen { size, paren } => {
+                let size = f(size);
+                NodeOf::SizedParen {
+                    size,
+                    paren: f(paren),
+                }
+            }
+            NodeOf::Matrix(grid) => {
+                NodeOf::Matrix(Box::new(grid.map(|node| node.map_text_with(f))))
+            }
+            NodeOf::Ampersand => NodeOf::Ampersand,
+            NodeOf::NewLine => NodeOf::NewLine,
+            NodeOf::Slashed(content) => NodeOf::Slashed(map(content)),
+            NodeOf::Style(style, content) => NodeOf::Style(style, map(content)),
+            NodeOf::Atom(class, content) => NodeOf::Atom(class, map(content)),
+            NodeOf::Undefined(token) => NodeOf::Undefined(token.map_text(&mut *f)),
+        }
+    }
+}
+
+impl<S: AsRef<str>> NodeOf<S> {
     /// Returns the inner string of the node if it is a Node that contains a string.
-    pub fn inner_str(&'a self) -> Option<&'a str> {
+    pub fn inner_str(&self) -> Option<&str> {
+        let s = match self {
+            NodeOf::Number(s)
+            | NodeOf::Letter(s, _)
+            | NodeOf::Operator(s)
+            | NodeOf::Function(s, _)
+            | NodeOf::OtherOperator(s)
+            | NodeOf::StrechedOp(_, s)
+            | NodeOf::UnderOp(s, _, _)
+            | NodeOf::OverOp(s, _, _) => s,
+            NodeOf::Text(s, _) | NodeOf::SizedParen { paren: s, .. } => s,
+            _ => return None,
+        };
+        Some(s.as_ref())
+    }
+
+    /// TeX's class of the atom the node makes, `None` for nodes such as spaces that aren't atoms
+    ///
+    /// Scripts and limits keep the class of their target, `\sum_i` being an `Op`. A `{x}` group is
+    /// an `Ord` and other fences are `Inner`, except for the unstretched braces of a set as in
+    /// `\{x\}`, which make several atoms and give `None`, see [`NodeOf::edge_classes`]. A parsed
+    /// `(x)` has the same tree as `\left( x \right)` and is an `Inner` as well.
+    pub fn atom_class(&self) -> Option<AtomClass> {
+        let class = match self {
+            NodeOf::Package(_)
+            | NodeOf::Title(_)
+            | NodeOf::Space(_)
+            | NodeOf::Ampersand
+            | NodeOf::NewLine => return None,
+            NodeOf::Operator(op) | NodeOf::OtherOperator(op) | NodeOf::StrechedOp(_, op) => {
+                glyph_class(op.as_ref()).unwrap_or(AtomClass::Ord)
+            }
+            NodeOf::SizedParen { paren, .. } => {
+                glyph_class(paren.as_ref()).unwrap_or(AtomClass::Ord)
+            }
+            NodeOf::Function(..) => AtomClass::Op,
+            // A big operator with only an upper limit, the others are accents
+            NodeOf::OverOp(op, _, _) | NodeOf::UnderOp(op, _, _) => {
+                match glyph_class(op.as_ref()) {
+                    Some(AtomClass::Op) => AtomClass::Op,
+                    _ => AtomClass::Ord,
+                }
+            }
+            NodeOf::Subscript(target, _)
+            | NodeOf::Superscript(target, _)
+            | NodeOf::SubSup { target, .. }
+            | NodeOf::Overset { target, .. }
+            | NodeOf::Underset { target, .. }
+            | NodeOf::UnderOver { target, .. } => return target.atom_class(),
+            NodeOf::Fenced { open, close, .. } => match (&**open, &**close) {
+                (NodeOf::StrechedOp(true, o), NodeOf::StrechedOp(true, c))
+                    if o.as_ref() == "{" && c.as_ref() == "}" =>
+                {
+                    AtomClass::Ord
+                }
+                (NodeOf::StrechedOp(false, _), NodeOf::StrechedOp(false, _)) => return None,
+                _ => AtomClass::Inner,
+            },
+            NodeOf::Frac(..) => AtomClass::Inner,
+            NodeOf::Style(_, content) => return content.atom_class(),
+            NodeOf::Atom(class, _) => *class,
+            NodeOf::Undefined(token) => return token.as_ref().map_text(AsRef::as_ref).atom_class(),
+            NodeOf::Number(_)
+            | NodeOf::Letter(..)
+            | NodeOf::Text(..)
+            | NodeOf::Sqrt(..)
+            | NodeOf::Row(_)
+            | NodeOf::Matrix(_)
+            | NodeOf::Slashed(_) => AtomClass::Ord,
+        };
+        Some(class)
+    }
+
+    /// Classes of the first and last atoms of the node, `\{x\}` giving `Open` and `Close`
+    pub fn edge_classes(&self) -> Option<(AtomClass, AtomClass)> {
         match self {
-            Node::Number(s)
-            | Node::Letter(s, _)
-            | Node::Operator(s)
-            | Node::Function(s, _)
-            | Node::OtherOperator(s)
-            | Node::StrechedOp(_, s)
-            | Node::UnderOp(s, _, _)
-            | Node::OverOp(s, _, _) => Some(s),
-            Node::Text(s, _) | Node::SizedParen { paren: s, .. } => Some(s),
-            _ => None,
+            NodeOf::Fenced { open, close, .. } if self.atom_class().is_none() => {
+                Some((open.atom_class()?, close.atom_class()?))
+            }
+            NodeOf::Subscript(target, _)
+            | NodeOf::Superscript(target, _)
+            | NodeOf::SubSup { target, .. }
+            | NodeOf::Overset { target, .. }
+            | NodeOf::Underset { target, .. }
+            | NodeOf::UnderOver { target, .. }
+            | NodeOf::Style(_, target) => target.edge_classes(),
+            _ => self.atom_class().map(|class| (class, class)),
         }
     }
 }
 
-// pub struct NodeIter<'a> {
-//   node: &'a Node<'a>,
-//   index: usize,
-// }
-
-// impl<'a> Iterator for NodeIter<'a> {
-//   type Item = &'a Node<'a>;
-
-//   fn next(&mut self) -> Option<Self::Item> {
-//     match self.node {
-//       Node::Row(nodes) => {
-//         if self.index < nodes.len() {
-//           let node = &nodes[self.index];
-//           self.index += 1;
-//           Some(node)
-//         } else {
-//           None
-//         }
-//       },
-//       node if self.index == 0 => {
-//         self.index += 1;
-//         Some(node)
-//       },
-//       _ => None,
-//     }
-//   }
-// }
-
-// impl<'a> Node<'a> {
-//   pub fn iter(&'a self) -> NodeIter<'a> {
-//     NodeIter {
-//       node: self,
-//       index: 0,
-//     }
-//   }
-
-//   pub fn len(&self) -> usize {
-//     match self {
-//       Node::Row(nodes) => nodes.len(),
-//       _ => 1,
-//     }
-//   }
-// }
+impl<S> NodeOf<S> {
+    /// Returns the `index`th child of the node, children are ordered as they appear in the source
+    pub fn child(&self, index: usize) -> Option<&NodeOf<S>> {
+        let child = match (self, index) {
+            (NodeOf::Row(nodes), index) => return nodes.get(index),
+            (NodeOf::Function(_, Some(arg)), 0) => arg,
+            (NodeOf::Subscript(target, _), 0) | (NodeOf::Superscript(target, _), 0) => target,
+            (NodeOf::Subscript(_, script), 1) | (NodeOf::Superscript(_, script), 1) => script,
+            (NodeOf::SubSup { target, .. }, 0) => target,
+            (NodeOf::SubSup { sub, .. }, 1) => sub,
+            (NodeOf::SubSup { sup, .. }, 2) => sup,
+            (NodeOf::OverOp(_, _, target), 0) | (NodeOf::UnderOp(_, _, target), 0) => target,
+            (NodeOf::Overset { over, .. }, 0) => over,
+            (NodeOf::Overset { target, .. }, 1) => target,
+            (NodeOf::Underset { under, .. }, 0) => under,
+            (NodeOf::Underset { target, .. }, 1) => target,
+            (NodeOf::UnderOver { target, .. }, 0) => target,
+            (NodeOf::UnderOver { under, .. }, 1) => under,
+            (NodeOf::UnderOver { over, .. }, 2) => over,
+            (NodeOf::Sqrt(Some(degree), _), 0) => degree,
+            (NodeOf::Sqrt(Some(_), content), 1) | (NodeOf::Sqrt(None, content), 0) => content,
+            (NodeOf::Frac(numerator, _, _), 0) => numerator,
+            (NodeOf::Frac(_, denominator, _), 1) => denominator,
+            (NodeOf::Fenced { open, .. }, 0) => open,
+            (NodeOf::Fenced { content, .. }, 1) => content,
+            (NodeOf::Fenced { close, .. }, 2) => close,
+            (NodeOf::Matrix(grid), index) => return grid.child(index),
+            (NodeOf::Slashed(content), 0)
+            | (NodeOf::Style(_, content), 0)
+            | (NodeOf::Atom(_, content), 0) => content,
+            _ => return None,
+        };
+        Some(child)
+    }
+
+    /// Iterates over the direct children of the node, see [`Node::child`]
+    pub fn children(&self) -> impl Iterator<Item = &NodeOf<S>> {
+        Children::new(self)
+    }
+
+    /// Iterates over the node and all of its descendants, children before their parents
+    pub fn post_order(&self) -> PostOrder<'_, S> {
+        PostOrder {
+            stack: alloc::vec![Children::new(self)],
+        }
+    }
+
+    /// Iterates depth first over the node and all of its descendants, parents before their
+    /// children
+    pub fn descendants(&self) -> Descendants<'_, S> {
+        Descendants {
+            stack: alloc::vec![self],
+        }
+    }
+
+    /// Number of nodes in the tree rooted at this node
+    #[cfg(test)]
+    pub(crate) fn node_count(&self) -> usize {
+        1 + self.children().map(NodeOf::node_count).sum::<usize>()
+    }
+}
+
+/// Post-order iterator returned by [`Node::post_order`]
+#[derive(Debug, Clone)]
+pub struct PostOrder<'n, S> {
+    stack: Vec<Children<'n, S>>,
+}
+
+impl<'n, S> Iterator for PostOrder<'n, S> {
+    type Item = &'n NodeOf<S>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let children = self.stack.last_mut()?;
+            match children.next() {
+                Some(child) => self.stack.push(Children::new(child)),
+                None => return self.stack.pop().map(|children| children.node),
+            }
+        }
+    }
+}
+
+/// Iterator over the children of a node, walking the rows of a matrix rather than looking up
+/// each cell by its index
+#[derive(Debug, Clone)]
+struct Children<'n, S> {
+    node: &'n NodeOf<S>,
+    index: usize,
+    /// Row and cell of the next cell of a matrix
+    cell: (usize, usize),
+}
+
+impl<'n, S> Children<'n, S> {
+    fn new(node: &'n NodeOf<S>) -> Self {
+        Children {
+            node,
+            index: 0,
+            cell: (0, 0),
+        }
+    }
+}
+
+impl<'n, S> Iterator for Children<'n, S> {
+    type Item = &'n NodeOf<S>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let NodeOf::Matrix(grid) = self.node else {
+            let child = self.node.child(self.index)?;
+            self.index += 1;
+            return Some(child);
+        };
+        if let Some((_, separator)) = grid.separators.get(self.index) {
+            self.index += 1;
+            return Some(separator);
+        }
+        loop {
+            let (row, cell) = &mut self.cell;
+            match grid.rows.get(*row)?.get(*cell) {
+                Some(child) => {
+                    *cell += 1;
+                    return Some(child);
+                }
+                None => self.cell = (*row + 1, 0),
+            }
+        }
+    }
+}
+
+/// Pre-order iterator returned by [`Node::descendants`]
+#[derive(Debug, Clone)]
+pub struct Descendants<'n, S> {
+    stack: Vec<&'n NodeOf<S>>,
+}
+
+impl<'n, S> Iterator for Descendants<'n, S> {
+    type Item = &'n NodeOf<S>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.stack.pop()?;
+        let start = self.stack.len();
+        self.stack.extend(node.children());
+        self.stack[start..].reverse();
+        Some(node)
+    }
+}
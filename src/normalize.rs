@@ -0,0 +1,160 @@
+use crate::{
+    models::{AtomClass, NodeBuf, NodeOf},
+    visit::Fold,
+};
+use alloc::{boxed::Box, string::String, vec::Vec};
+
+/// Rewrites `node` into a canonical form, so that the ways of writing a formula compare equal
+///
+/// - `{}` groups are replaced by their content, `\sqrt{x}` reads as `\sqrt x`, except for the
+///   target of a script that would bind differently without them, as in `{x^a}_b`
+/// - rows are flattened into the rows holding them, and a row of one node becomes that node
+/// - adjacent numbers are merged, `1 2` reads as `12`
+/// - scripts added to a scripted node are attached to its base, `x'_1` reads as a `SubSup`, and
+///   `\underset{a}{\overset{b}{x}}` as the limits of `x`
+///
+/// Merging numbers builds new text, so the normalized node owns its text.
+pub fn normalize<S: AsRef<str>>(node: NodeOf<S>) -> NodeBuf {
+    Normalizer.fold_node(node.map_text(|text| String::from(text.as_ref())))
+}
+
+struct Normalizer;
+
+impl Normalizer {
+    /// Appends a normalized node to a row, flattening and merging it into the row
+    fn push(nodes: &mut Vec<NodeBuf>, node: NodeBuf) {
+        match (nodes.last_mut(), node) {
+            (_, NodeOf::Row(row)) => {
+                for node in row {
+                    Self::push(nodes, node);
+                }
+            }
+            (Some(NodeOf::Number(last)), NodeOf::Number(number)) => {
+                last.push_str(&number);
+            }
+            (_, node) => nodes.push(node),
+        }
+    }
+
+    /// Normalizes the target of a script, keeping the braces of a `{}` group whose content would
+    /// take the script differently, as in `{x^a}_b` or `{\sum}_i`
+    fn script_target(&mut self, target: Box<NodeBuf>) -> Box<NodeBuf> {
+        let NodeOf::Fenced {
+            open,
+            content,
+            close,
+        } = *target
+        else {
+            return self.fold_box(target);
+        };
+        if !is_group(&open, &close) {
+            return self.fold_fenced(open, content, close).into();
+        }
+        let content = self.fold_box(content);
+        let binds = matches!(
+            *content,
+            NodeOf::Subscript(..)
+                | NodeOf::Superscript(..)
+                | NodeOf::SubSup { .. }
+                | NodeOf::Overset { .. }
+                | NodeOf::Underset { .. }
+                | NodeOf::UnderOver { .. }
+        ) || content.atom_class() == Some(AtomClass::Op);
+        match binds {
+            true => Box::new(NodeOf::Fenced {
+                open,
+                content,
+                close,
+            }),
+            false => content,
+        }
+    }
+}
+
+/// Whether the delimiters are the braces of a `{}` group
+fn is_group(open: &NodeBuf, close: &NodeBuf) -> bool {
+    matches!(
+        (open, close),
+        (NodeOf::StrechedOp(true, o), NodeOf::StrechedOp(true, c)) if o == "{" && c == "}"
+    )
+}
+
+impl Fold<String> for Normalizer {
+    fn fold_row(&mut self, nodes: Vec<NodeBuf>) -> NodeBuf {
+        let mut row = Vec::with_capacity(nodes.len());
+        for node in nodes {
+            let node = self.fold_node(node);
+            Self::push(&mut row, node);
+        }
+        match <[_; 1]>::try_from(row) {
+            Ok([node]) => node,
+            Err(row) => NodeOf::Row(row),
+        }
+    }
+
+    fn fold_fenced(
+        &mut self,
+        open: Box<NodeBuf>,
+        content: Box<NodeBuf>,
+        close: Box<NodeBuf>,
+    ) -> NodeBuf {
+        match (&*open, &*close) {
+            _ if is_group(&open, &close) => self.fold_node(*content),
+            _ => NodeOf::Fenced {
+                open,
+                content: self.fold_box(content),
+                close,
+            },
+        }
+    }
+
+    fn fold_subscript(&mut self, target: Box<NodeBuf>, sub: Box<NodeBuf>) -> NodeBuf {
+        let target = self.script_target(target);
+        let sub = self.fold_box(sub);
+        match *target {
+            NodeOf::Superscript(target, sup) => NodeOf::SubSup { target, sub, sup },
+            base => NodeOf::Subscript(Box::new(base), sub),
+        }
+    }
+
+    fn fold_superscript(&mut self, target: Box<NodeBuf>, sup: Box<NodeBuf>) -> NodeBuf {
+        let target = self.script_target(target);
+        let sup = self.fold_box(sup);
+        match *target {
+            NodeOf::Subscript(target, sub) => NodeOf::SubSup { target, sub, sup },
+            base => NodeOf::Superscript(Box::new(base), sup),
+        }
+    }
+
+    fn fold_overset(&mut self, over: Box<NodeBuf>, target: Box<NodeBuf>) -> NodeBuf {
+        let over = self.fold_box(over);
+        let target = self.fold_box(target);
+        match *target {
+            NodeOf::Underset { under, target } => NodeOf::UnderOver {
+                target,
+                under,
+                over,
+            },
+            base => NodeOf::Overset {
+                over,
+                target: Box::new(base),
+            },
+        }
+    }
+
+    fn fold_underset(&mut self, under: Box<NodeBuf>, target: Box<NodeBuf>) -> NodeBuf {
+        let under = self.fold_box(under);
+        let target = self.fold_box(target);
+        match *target {
+            NodeOf::Overset { over, target } => NodeOf::UnderOver {
+                target,
+                under,
+                over,
+            },
+            base => NodeOf::Underset {
+                under,
+                target: Box::new(base),
+            },
+        }
+    }
+}
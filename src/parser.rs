@@ -1,411 +1,1143 @@
 use crate::{
+    ast::{Ast, AstBuilder},
+    error::{Diagnostic, ParseError, ParseErrorKind, Recovery},
     lexer::Lexer,
-    models::{Accent, ColumnAlign, LineThickness, Node, Variant},
+    macros::{Expander, MacroTable, Source},
+    models::{
+        Accent, AtomClass, ColumnAlign, Dimension, DisplayStyle, Grid, HLine, LineThickness,
+        MultiColumn, Node, Variant,
+    },
+    span::{Span, SpanTable},
     token::Token,
 };
 
 use alloc::boxed::Box;
 use alloc::vec::Vec;
+use core::ops::Range;
 
 #[derive(Debug, Clone)]
 pub struct Parser<'a> {
-    lexer: Lexer<'a>,
+    tokens: Expander<'a>,
     cur: Token<'a>,
     peek: Token<'a>,
+    cur_span: Span,
+    peek_span: Span,
+    cur_source: Source<'a>,
+    peek_source: Source<'a>,
+    /// Spans of the nodes built so far, in post-order
+    spans: Vec<Span>,
+    /// Whether errors are collected into `diagnostics` instead of being returned
+    recover: bool,
+    diagnostics: Vec<Diagnostic<'a>>,
 }
 
 impl<'a> Iterator for Parser<'a> {
     type Item = Node<'a>;
 
+    /// Recovers from malformed input like [`Parser::parse`]
     fn next(&mut self) -> Option<Self::Item> {
-        let node = self.next_node();
-        self.next_token();
-        if let Node::Undefined(Token::EOF) = node {
-            None
-        } else {
-            Some(node)
-        }
+        self.spans.clear();
+        self.diagnostics.clear();
+        self.parse_next(&mut Boxed).expect(RECOVERING)
     }
 }
 
 impl<'a> Parser<'a> {
     pub fn new(input: &'a str) -> Self {
-        let mut lexer = Lexer::new(input);
-        Self {
-            cur: lexer.next_token(),
-            peek: lexer.next_token(),
-            lexer,
+        Self::from_tokens(Expander::new(input, None))
+    }
+
+    /// Creates a parser expanding the macros of `macros` on top of the ones defined in the input
+    pub fn with_macros(input: &'a str, macros: &'a MacroTable) -> Self {
+        Self::from_tokens(Expander::new(input, Some(macros)))
+    }
+
+    fn from_tokens(mut tokens: Expander<'a>) -> Self {
+        let (first, source) = tokens.next_spanned();
+        let mut parser = Self {
+            cur: Token::EOF,
+            peek: first.value,
+            cur_span: Span::default(),
+            peek_span: first.span,
+            cur_source: source,
+            peek_source: source,
+            spans: Vec::new(),
+            recover: true,
+            diagnostics: Vec::new(),
+            tokens,
+        };
+        // Reads the second token the same way as any other, so `\frac12` works at the start
+        parser.next_token();
+        parser
+    }
+
+    /// Parses the whole input, making the best of malformed input
+    ///
+    /// Use [`Parser::try_parse`] or [`Parser::parse_recovering`] to find out about errors
+    pub fn parse(self) -> Vec<Node<'a>> {
+        self.parse_recovering().0
+    }
+
+    /// Parses the whole input into an [`Ast`] arena, making the best of malformed input
+    ///
+    /// Nodes are built in the arena as they are parsed, without boxing them first.
+    pub fn parse_ast(mut self) -> Ast<&'a str> {
+        let mut builder = AstBuilder::default();
+        while let Some(root) = self.parse_next(&mut builder).expect(RECOVERING) {
+            builder.push_root(root);
         }
+        builder.finish()
+    }
+
+    /// Parses the whole input, carrying on after every error
+    ///
+    /// Returns the best-effort tree along with a [`Diagnostic`] for each error recovered from
+    pub fn parse_recovering(mut self) -> (Vec<Node<'a>>, Vec<Diagnostic<'a>>) {
+        self.recover = true;
+        let nodes = self.parse_all().expect(RECOVERING);
+        (nodes, self.diagnostics)
     }
 
-    pub fn parse(mut self) -> Vec<Node<'a>> {
+    /// Parses the whole input, returning the first error encountered
+    pub fn try_parse(self) -> Result<Vec<Node<'a>>, ParseError<'a>> {
+        self.try_parse_spanned().map(|(nodes, _)| nodes)
+    }
+
+    /// Parses the whole input along with the span of every node in the tree
+    pub fn try_parse_spanned(mut self) -> Result<(Vec<Node<'a>>, SpanTable), ParseError<'a>> {
+        self.recover = false;
+        let nodes = self.parse_all()?;
+        Ok((nodes, SpanTable { spans: self.spans }))
+    }
+
+    fn parse_all(&mut self) -> Result<Vec<Node<'a>>, ParseError<'a>> {
         let mut nodes = Vec::new();
-        while self.cur != Token::EOF {
-            nodes.push(self.next_node());
+        while let Some(node) = self.parse_next(&mut Boxed)? {
+            nodes.push(node);
+        }
+        Ok(nodes)
+    }
+
+    /// Parses the next top level node into `sink`, returns `None` at the end of the input
+    fn parse_next<K: Sink<'a>>(&mut self, sink: &mut K) -> Result<Option<K::Node>, ParseError<'a>> {
+        self.flush()?;
+        while self.skip_stray()? {
             self.next_token();
         }
-        nodes
+        if self.cur == Token::EOF {
+            return Ok(None);
+        }
+        let node = self.next_node(sink)?;
+        self.next_token();
+        Ok(Some(node))
     }
 
     fn next_token(&mut self) {
         self.cur = self.peek;
-        self.peek = if self.cur.acts_on_a_digit() && self.lexer.cur.is_ascii_digit() {
-            let c = self.lexer.read_char();
-            Token::Number(self.lexer.grab_slice(c.len_utf8()))
+        self.cur_span = self.peek_span;
+        self.cur_source = self.peek_source;
+        let digit = if self.cur.acts_on_a_digit() {
+            self.tokens.next_digit()
         } else {
-            self.lexer.next_token()
+            None
         };
+        let (peek, source) = digit.unwrap_or_else(|| self.tokens.next_spanned());
+        self.peek = peek.value;
+        self.peek_span = peek.span;
+        self.peek_source = source;
+    }
+
+    fn error(&self, kind: ParseErrorKind<'a>, expected: Option<Token<'a>>) -> ParseError<'a> {
+        ParseError {
+            kind,
+            span: self.cur_span,
+            expected,
+            found: self.cur,
+        }
+    }
+
+    /// Returns `error` when failing fast, otherwise keeps it as a diagnostic so parsing can go on
+    fn fail(&mut self, error: ParseError<'a>, recovery: Recovery) -> Result<(), ParseError<'a>> {
+        if !self.recover {
+            return Err(error);
+        }
+        self.diagnostics.push(Diagnostic { error, recovery });
+        Ok(())
+    }
+
+    /// Reports the errors found while expanding macros
+    fn flush(&mut self) -> Result<(), ParseError<'a>> {
+        for diagnostic in self.tokens.take_errors() {
+            self.fail(diagnostic.error, diagnostic.recovery)?;
+        }
+        Ok(())
+    }
+
+    /// Skips a `\right` or `\end` that does not close any group along with its argument,
+    /// returns whether anything was skipped
+    fn skip_stray(&mut self) -> Result<bool, ParseError<'a>> {
+        if !matches!(self.cur, Token::Right | Token::End) {
+            return Ok(false);
+        }
+        self.fail(
+            self.error(ParseErrorKind::UnexpectedToken, None),
+            Recovery::Skipped,
+        )?;
+        if self.cur == Token::End {
+            if self.peek == Token::LSeperator("{") {
+                self.next_token();
+                self.parse_text(Token::RSeperator("}"))?;
+            }
+            return Ok(true);
+        }
+        if let Token::Big(_) = self.peek {
+            self.next_token();
+        }
+        if let Token::Paren(_) | Token::LSeperator(_) | Token::RSeperator(_) = self.peek {
+            self.next_token();
+        }
+        Ok(true)
+    }
+
+    /// Records the span of a node starting at `start` and ending with the current token
+    fn record(&mut self, start: usize) {
+        self.spans.push(Span::new(start, self.cur_span.end));
+    }
+
+    /// Moves the spans recorded after `split` in front of the ones recorded since `mark`,
+    /// for nodes whose children are not built in the order they are stored
+    fn rotate(&mut self, mark: usize, split: usize) {
+        let moved = self.spans.len() - split;
+        self.spans[mark..].rotate_right(moved);
+    }
+
+    /// Unwraps a `{}` group around an argument whose spans were recorded since `mark`,
+    /// dropping the spans of the braces
+    fn arg<K: Sink<'a>>(&mut self, sink: &mut K, node: K::Node, mark: usize) -> K::Node {
+        match sink.unwrap_group(node) {
+            Ok(content) => {
+                self.spans.truncate(self.spans.len() - 2);
+                self.spans.remove(mark);
+                content
+            }
+            Err(node) => node,
+        }
+    }
+
+    /// Collects the argument of a command with [`Parser::single_node`]
+    fn single_argument<K: Sink<'a>>(&mut self, sink: &mut K) -> Result<K::Node, ParseError<'a>> {
+        if self.cur == Token::EOF {
+            // Recovering leaves an `Undefined(EOF)` node in place of the argument
+            self.fail(
+                self.error(ParseErrorKind::MissingArgument, None),
+                Recovery::Substituted,
+            )?;
+        }
+        self.single_node(sink)
+    }
+
+    /// Collects the argument of a command with [`Parser::next_node`]
+    fn next_argument<K: Sink<'a>>(&mut self, sink: &mut K) -> Result<K::Node, ParseError<'a>> {
+        if self.cur == Token::EOF {
+            // Recovering leaves an `Undefined(EOF)` node in place of the argument
+            self.fail(
+                self.error(ParseErrorKind::MissingArgument, None),
+                Recovery::Substituted,
+            )?;
+        }
+        self.next_node(sink)
+    }
+
+    /// Like [`Parser::single_argument`], unwrapping the argument if it is a `{}` group
+    fn single_arg<K: Sink<'a>>(&mut self, sink: &mut K) -> Result<K::Node, ParseError<'a>> {
+        let mark = self.spans.len();
+        let node = self.single_argument(sink)?;
+        Ok(self.arg(sink, node, mark))
     }
 
-    fn parse_group(&mut self, end: Token) -> Option<Node<'a>> {
+    /// Like [`Parser::next_argument`], unwrapping the argument if it is a `{}` group
+    fn next_arg<K: Sink<'a>>(&mut self, sink: &mut K) -> Result<K::Node, ParseError<'a>> {
+        let mark = self.spans.len();
+        let node = self.next_argument(sink)?;
+        Ok(self.arg(sink, node, mark))
+    }
+
+    /// Parses nodes until `end`, returns `None` if the group is empty
+    ///
+    /// When recovering, a group still open at the end of the input is closed there.
+    fn parse_group<K: Sink<'a>>(
+        &mut self,
+        sink: &mut K,
+        end: Token<'a>,
+    ) -> Result<Option<K::Node>, ParseError<'a>> {
         self.next_token();
+        let start = self.cur_span.start;
+        let mut last = start;
         let mut nodes = Vec::new();
         while self.cur != end {
             if self.cur == Token::EOF {
-                return None;
+                self.fail(
+                    self.error(ParseErrorKind::UnterminatedGroup, Some(end)),
+                    Recovery::ClosedGroup,
+                )?;
+                break;
             }
-            nodes.push(self.next_node());
+            if self.skip_stray()? {
+                self.next_token();
+                continue;
+            }
+            nodes.push(self.next_node(sink)?);
+            last = self.cur_span.end;
             self.next_token();
         }
-        if nodes.is_empty() {
+        Ok(if nodes.is_empty() {
             None
         } else if nodes.len() == 1 {
             nodes.pop()
         } else {
-            Some(Node::Row(nodes))
+            self.spans.push(Span::new(start, last));
+            Some(sink.push(Shape::Row(nodes)))
+        })
+    }
+
+    /// Parses the cells of an environment up to its `\end` into `grid`, a row ending at
+    /// every `\\`
+    ///
+    /// A last row left empty by a trailing `\\` is dropped.
+    fn parse_grid<K: Sink<'a>>(
+        &mut self,
+        sink: &mut K,
+        grid: &mut Grid<K::Node>,
+    ) -> Result<(), ParseError<'a>> {
+        let mut row = Vec::new();
+        loop {
+            let cell = self.parse_cell(sink, grid, row.len())?;
+            row.push(cell);
+            match self.cur {
+                Token::Ampersand => {}
+                Token::NewLine | Token::NewLineNoPgBreak => {
+                    let spacing = if self.peek == Token::LSeperator("[") {
+                        self.next_token();
+                        self.dimension(Token::RSeperator("]"))?
+                    } else {
+                        None
+                    };
+                    grid.rows.push(core::mem::take(&mut row));
+                    grid.row_spacing.push(spacing);
+                }
+                _ => break,
+            }
+        }
+        if matches!(&row[..], [cell] if sink.is_empty_row(cell)) {
+            self.spans.pop();
+            sink.discard(row.pop().unwrap());
+        } else {
+            grid.rows.push(row);
+            grid.row_spacing.push(None);
         }
+        Ok(())
     }
 
-    fn parse_text(&mut self, end: Token) -> &'a str {
+    /// Parses the nodes of the `cell`th cell of the next row of `grid` up to the `&`, `\\` or
+    /// `\end` after it, an empty cell being an empty row
+    ///
+    /// The `\hline` and `\cline` rules before the first cell of a row are added to `grid`, as
+    /// is a `\multicolumn` starting the cell.
+    fn parse_cell<K: Sink<'a>>(
+        &mut self,
+        sink: &mut K,
+        grid: &mut Grid<K::Node>,
+        cell: usize,
+    ) -> Result<K::Node, ParseError<'a>> {
+        self.next_token();
+        let row = grid.rows.len();
+        while cell == 0 && matches!(self.cur, Token::HLine | Token::CLine) {
+            if self.cur == Token::HLine {
+                grid.hlines.push(HLine { row, columns: None });
+            } else if let Some(columns) = self.cline()? {
+                grid.hlines.push(HLine {
+                    row,
+                    columns: Some(columns),
+                });
+            }
+            self.next_token();
+        }
+        let start = self.cur_span.start;
+        let mut last = start;
+        let mut nodes = Vec::new();
+        if self.cur == Token::MultiColumn {
+            let content = self.multicolumn(sink, grid, cell)?;
+            nodes.push(content);
+            last = self.cur_span.end;
+            self.next_token();
+        }
+        while !matches!(
+            self.cur,
+            Token::End | Token::Ampersand | Token::NewLine | Token::NewLineNoPgBreak
+        ) {
+            if self.cur == Token::EOF {
+                self.fail(
+                    self.error(ParseErrorKind::UnterminatedGroup, Some(Token::End)),
+                    Recovery::ClosedGroup,
+                )?;
+                break;
+            }
+            if self.skip_stray()? {
+                self.next_token();
+                continue;
+            }
+            nodes.push(self.next_node(sink)?);
+            last = self.cur_span.end;
+            self.next_token();
+        }
+        if nodes.len() == 1 {
+            return Ok(nodes.pop().unwrap());
+        }
+        self.spans.push(Span::new(start, last));
+        Ok(sink.push(Shape::Row(nodes)))
+    }
+
+    /// Reads the `{2-3}` after a `\cline` as the first and last columns it spans, counted
+    /// from 0, an invalid one is dropped when recovering
+    fn cline(&mut self) -> Result<Option<(usize, usize)>, ParseError<'a>> {
+        self.next_token();
+        let start = self.peek_span.start;
+        let text = self.parse_text(Token::RSeperator("}"))?;
+        let columns = text.split_once('-').and_then(|(first, last)| {
+            let first = first.trim().parse::<usize>().ok()?;
+            let last = last.trim().parse::<usize>().ok()?;
+            (1 <= first && first <= last).then(|| (first - 1, last - 1))
+        });
+        if columns.is_none() {
+            let error = ParseError {
+                span: Span::new(start, self.cur_span.start),
+                ..self.error(ParseErrorKind::InvalidColumnSpec(text), None)
+            };
+            self.fail(error, Recovery::Skipped)?;
+        }
+        Ok(columns)
+    }
+
+    /// Reads a `\multicolumn{2}{c}{content}` starting the `cell`th cell of the next row of
+    /// `grid`, returns its content
+    fn multicolumn<K: Sink<'a>>(
+        &mut self,
+        sink: &mut K,
+        grid: &mut Grid<K::Node>,
+        cell: usize,
+    ) -> Result<K::Node, ParseError<'a>> {
+        self.next_token();
+        let count = self.count()?;
+        let start = self.peek_source;
+        let mark = self.spans.len();
+        let mut spec = Grid::default();
+        self.column_spec(sink, &mut spec)?;
+        // The cell takes a single column, without separators
+        if spec.columns.len() != 1 || !spec.separators.is_empty() {
+            self.spans.truncate(mark);
+            let text = if core::ptr::eq(start.text, self.cur_source.text) {
+                &start.text[start.span.start..self.cur_source.span.end]
+            } else {
+                ""
+            };
+            let error = ParseError {
+                span: Span::new(start.span.start, self.cur_span.end),
+                ..self.error(ParseErrorKind::InvalidColumnSpec(text), None)
+            };
+            self.fail(error, Recovery::Skipped)?;
+        }
+        self.next_token();
+        let content = self.single_arg(sink)?;
+        grid.multicolumns.push(MultiColumn {
+            row: grid.rows.len(),
+            cell,
+            columns: count,
+            align: spec.columns.first().copied().unwrap_or(ColumnAlign::Center),
+            width: spec.widths.first().copied().flatten(),
+            vlines: spec.vlines,
+        });
+        Ok(content)
+    }
+
+    /// Reads the number of columns in braces of a `\multicolumn`, an invalid one is read as 1
+    /// when recovering
+    fn count(&mut self) -> Result<usize, ParseError<'a>> {
+        let start = self.peek_span.start;
+        let text = self.parse_text(Token::RSeperator("}"))?;
+        match text.trim().parse::<usize>() {
+            Ok(count) if count <= MAX_COLUMNS => Ok(count),
+            _ => {
+                let error = ParseError {
+                    span: Span::new(start, self.cur_span.start),
+                    ..self.error(ParseErrorKind::InvalidColumnSpec(text), None)
+                };
+                self.fail(error, Recovery::Substituted)?;
+                Ok(1)
+            }
+        }
+    }
+
+    /// Reads the column specification in braces after the current token, such as the `{c|c|l}`
+    /// of an `array`, into the columns, rules and separators of `grid`
+    fn column_spec<K: Sink<'a>>(
+        &mut self,
+        sink: &mut K,
+        grid: &mut Grid<K::Node>,
+    ) -> Result<(), ParseError<'a>> {
+        let mark = self.spans.len();
+        self.spec_columns(sink, grid, mark)
+    }
+
+    /// Like [`Parser::column_spec`], the spans of the separators of `grid` starting at `mark`
+    fn spec_columns<K: Sink<'a>>(
+        &mut self,
+        sink: &mut K,
+        grid: &mut Grid<K::Node>,
+        mark: usize,
+    ) -> Result<(), ParseError<'a>> {
+        if self.peek != Token::LSeperator("{") {
+            let error = ParseError {
+                span: self.peek_span,
+                found: self.peek,
+                ..self.error(
+                    ParseErrorKind::UnexpectedToken,
+                    Some(Token::LSeperator("{")),
+                )
+            };
+            return self.fail(error, Recovery::Skipped);
+        }
+        self.next_token();
+        loop {
+            self.next_token();
+            match self.cur {
+                Token::RSeperator("}") => return Ok(()),
+                Token::EOF => {
+                    return self.fail(
+                        self.error(
+                            ParseErrorKind::UnterminatedGroup,
+                            Some(Token::RSeperator("}")),
+                        ),
+                        Recovery::ClosedGroup,
+                    );
+                }
+                Token::Letter(column @ ("l" | "c" | "r"), _) => {
+                    grid.columns.push(match column {
+                        "l" => ColumnAlign::Left,
+                        "c" => ColumnAlign::Center,
+                        _ => ColumnAlign::Right,
+                    });
+                    grid.widths.push(None);
+                }
+                Token::Letter("p", _) if self.peek == Token::LSeperator("{") => {
+                    self.next_token();
+                    let width = self.dimension(Token::RSeperator("}"))?;
+                    grid.columns.push(ColumnAlign::Left);
+                    grid.widths.push(width);
+                }
+                Token::Paren("|") => grid.vlines.push(grid.columns.len()),
+                Token::Letter("@", _) => {
+                    self.next_token();
+                    let separator = self.single_arg(sink)?;
+                    grid.separators.push((grid.columns.len(), separator));
+                }
+                Token::Operator("*") if self.peek == Token::LSeperator("{") => {
+                    self.next_token();
+                    self.repeat_spec(sink, grid, mark)?;
+                }
+                _ => {
+                    let text = &self.cur_source.text[Range::from(self.cur_source.span)];
+                    self.fail(
+                        self.error(ParseErrorKind::InvalidColumnSpec(text), None),
+                        Recovery::Skipped,
+                    )?;
+                }
+            }
+        }
+    }
+
+    /// Reads the `{count}{spec}` of a `*{count}{spec}` into `grid`, the spec `count` times over
+    ///
+    /// A count taking the specification past [`MAX_COLUMNS`] entries is invalid, and read as 1
+    /// when recovering.
+    fn repeat_spec<K: Sink<'a>>(
+        &mut self,
+        sink: &mut K,
+        grid: &mut Grid<K::Node>,
+        mark: usize,
+    ) -> Result<(), ParseError<'a>> {
+        let start = self.peek_span.start;
+        let text = self.parse_text(Token::RSeperator("}"))?;
+        let span = Span::new(start, self.cur_span.start);
+        let (columns, vlines) = (grid.columns.len(), grid.vlines.len());
+        let (separators, spans) = (grid.separators.len(), self.spans.len());
+        self.spec_columns(sink, grid, mark)?;
+        // Columns, rules and the nodes of separators all take memory
+        let size = grid.columns.len() + grid.vlines.len() + self.spans.len() - mark;
+        let block = size - (columns + vlines + spans - mark);
+        let count = text.trim().parse::<usize>().ok().filter(|&count| {
+            block
+                .checked_mul(count.saturating_sub(1))
+                .and_then(|added| added.checked_add(size))
+                .is_some_and(|size| size <= MAX_COLUMNS)
+        });
+        let count = match count {
+            Some(count) => count,
+            None => {
+                let error = ParseError {
+                    span,
+                    ..self.error(ParseErrorKind::InvalidColumnSpec(text), None)
+                };
+                self.fail(error, Recovery::Substituted)?;
+                1
+            }
+        };
+        let added = grid.columns.len() - columns;
+        let repeated_vlines = grid.vlines[vlines..].to_vec();
+        let repeated_separators = grid.separators[separators..].to_vec();
+        let repeated_spans = self.spans[spans..].to_vec();
+        if count == 0 {
+            grid.columns.truncate(columns);
+            grid.widths.truncate(columns);
+            grid.vlines.truncate(vlines);
+            grid.separators.truncate(separators);
+            self.spans.truncate(spans);
+        }
+        for repeat in 1..count {
+            let offset = repeat * added;
+            grid.columns.extend_from_within(columns..columns + added);
+            grid.widths.extend_from_within(columns..columns + added);
+            grid.vlines
+                .extend(repeated_vlines.iter().map(|column| column + offset));
+            grid.separators.extend(
+                repeated_separators
+                    .iter()
+                    .map(|(column, separator)| (column + offset, separator.clone())),
+            );
+            self.spans.extend_from_slice(&repeated_spans);
+        }
+        Ok(())
+    }
+
+    /// Reads a length such as the `2pt` of `\\[2pt]` up to `end`, an invalid one is dropped
+    /// when recovering
+    fn dimension(&mut self, end: Token<'a>) -> Result<Option<Dimension>, ParseError<'a>> {
+        let start = self.peek_span.start;
+        let text = self.parse_text(end)?;
+        let dimension = Dimension::parse(text);
+        if dimension.is_none() {
+            let error = ParseError {
+                span: Span::new(start, self.cur_span.start),
+                ..self.error(ParseErrorKind::InvalidDimension(text), None)
+            };
+            self.fail(error, Recovery::Skipped)?;
+        }
+        Ok(dimension)
+    }
+
+    /// Reads the text up to `end` as is, which is only followed within the macro body or
+    /// argument the text starts in
+    fn parse_text(&mut self, end: Token<'a>) -> Result<&'a str, ParseError<'a>> {
         // self.next_token(); // skip {
-        let start = self.lexer.index - self.lexer.cur.len_utf8();
+        let text = self.peek_source.text;
+        let start = self.peek_source.span.start;
+        let mut last = start;
         while self.peek != end {
+            if self.peek == Token::EOF {
+                self.next_token();
+                self.fail(
+                    self.error(ParseErrorKind::UnterminatedGroup, Some(end)),
+                    Recovery::ClosedGroup,
+                )?;
+                return Ok(&text[start..last]);
+            }
             self.next_token();
+            if core::ptr::eq(self.cur_source.text, text) {
+                last = self.cur_source.span.end;
+            }
+        }
+        if core::ptr::eq(self.peek_source.text, text) {
+            last = self.peek_source.span.start;
         }
-        let end = self.lexer.index - self.lexer.cur.len_utf8();
         self.next_token();
         // self.next_token();
-        &self.lexer.input[start..end]
+        Ok(&text[start..last])
+    }
+
+    /// Reads the delimiter following `\left`, `\right` or a `\big` size command, `.` being
+    /// read as empty, an invalid one is skipped and read as empty when recovering
+    fn delimiter(&mut self) -> Result<(Option<&'a str>, &'a str), ParseError<'a>> {
+        let size = match self.cur {
+            Token::Big(size) => {
+                self.next_token();
+                Some(size)
+            }
+            _ => None,
+        };
+        match self.cur {
+            Token::Paren(paren) | Token::LSeperator(paren) | Token::RSeperator(paren) => {
+                Ok((size, paren))
+            }
+            // `.` stands for no delimiter at all
+            Token::Operator(".") => Ok((size, "")),
+            _ => {
+                self.fail(
+                    self.error(ParseErrorKind::InvalidDelimiter, None),
+                    Recovery::Skipped,
+                )?;
+                Ok((size, ""))
+            }
+        }
     }
 
     /// Collects the next node from the input, checking for subscripts and superscripts thereafter
-    fn next_node(&mut self) -> Node<'a> {
-        let left = self.single_node();
-        match self.peek {
+    fn next_node<K: Sink<'a>>(&mut self, sink: &mut K) -> Result<K::Node, ParseError<'a>> {
+        let start = self.cur_span.start;
+        let left = self.single_node(sink)?;
+        let node = match self.peek {
             Token::Underscore => {
                 self.next_token();
                 self.next_token();
-                let right = self.single_node().arg();
+                let right = self.single_arg(sink)?;
                 match self.peek {
                     Token::Circumflex => {
                         self.next_token();
                         self.next_token();
-                        let upper = self.single_node().arg();
-                        Node::SubSup {
-                            target: left.into(),
+                        let upper = self.single_arg(sink)?;
+                        Shape::SubSup {
+                            target: left,
                             sub: right,
                             sup: upper,
                         }
                     }
-                    _ => Node::Subscript(Box::new(left), right),
+                    _ => Shape::Subscript(left, right),
                 }
             }
             Token::Circumflex => {
                 self.next_token();
                 self.next_token();
-                let right = self.single_node().arg();
+                let mark = self.spans.len();
+                let right = self.single_arg(sink)?;
                 match self.peek {
                     Token::Underscore => {
                         self.next_token();
                         self.next_token();
-                        let lower = self.single_node().arg();
-                        Node::SubSup {
-                            target: left.into(),
+                        let split = self.spans.len();
+                        let lower = self.single_arg(sink)?;
+                        self.rotate(mark, split);
+                        Shape::SubSup {
+                            target: left,
                             sub: lower,
                             sup: right,
                         }
                     }
-                    _ => Node::Superscript(Box::new(left), right),
+                    _ => Shape::Superscript(left, right),
                 }
             }
-            _ => left,
+            _ => return Ok(left),
+        };
+        self.record(start);
+        Ok(sink.push(node))
+    }
+
+    /// The operator named `name`, a row of the names and spaces of `name` if it holds
+    /// spacing commands such as `arg\,max`, their spans recorded as `span`
+    fn operator_name<K: Sink<'a>>(&mut self, sink: &mut K, name: &'a str, span: Span) -> K::Node {
+        let mut nodes = Vec::new();
+        let mut text_start = 0;
+        let mut lexer = Lexer::new(name);
+        loop {
+            let token = lexer.next_spanned();
+            let text = name[text_start..token.span.start].trim();
+            match token.value {
+                Token::Space(space) => {
+                    if !text.is_empty() {
+                        nodes.push(sink.leaf(Node::Function(text, None)));
+                    }
+                    nodes.push(sink.leaf(Node::Space(space)));
+                    text_start = token.span.end;
+                }
+                Token::EOF if nodes.is_empty() => return sink.leaf(Node::Function(name, None)),
+                Token::EOF => {
+                    if !text.is_empty() {
+                        nodes.push(sink.leaf(Node::Function(text, None)));
+                    }
+                    break;
+                }
+                _ => {}
+            }
         }
+        self.spans.extend(nodes.iter().map(|_| span));
+        sink.push(Shape::Row(nodes))
+    }
+
+    /// Places the scripts following an operator with limits, such as `\lim` or
+    /// `\operatorname*`, under and over `target` instead of beside it
+    ///
+    /// The spans of the children of `target` are the ones recorded since `target_mark`, the
+    /// span of `target` itself is `span`.
+    fn limits<K: Sink<'a>>(
+        &mut self,
+        sink: &mut K,
+        target: K::Node,
+        span: Span,
+        target_mark: usize,
+    ) -> Result<K::Node, ParseError<'a>> {
+        let mark = self.spans.len();
+        let node = match self.peek {
+            Token::Underscore => {
+                self.next_token();
+                self.next_token();
+                let under = self.single_arg(sink)?;
+                if self.peek == Token::Circumflex {
+                    self.next_token();
+                    self.next_token();
+                    let over = self.single_arg(sink)?;
+                    self.spans.insert(mark, span);
+                    Shape::UnderOver {
+                        target,
+                        under,
+                        over,
+                    }
+                } else {
+                    self.rotate(target_mark, mark);
+                    self.spans.push(span);
+                    Shape::Underset { under, target }
+                }
+            }
+            Token::Circumflex => {
+                self.next_token();
+                self.next_token();
+                let over = self.single_arg(sink)?;
+                if self.peek == Token::Underscore {
+                    self.next_token();
+                    self.next_token();
+                    let split = self.spans.len();
+                    let under = self.single_arg(sink)?;
+                    self.rotate(mark, split);
+                    self.spans.insert(mark, span);
+                    Shape::UnderOver {
+                        target,
+                        under,
+                        over,
+                    }
+                } else {
+                    self.rotate(target_mark, mark);
+                    self.spans.push(span);
+                    Shape::Overset { over, target }
+                }
+            }
+            _ => return Ok(target),
+        };
+        Ok(sink.push(node))
     }
 
     /// Collects a single node from the input, does not check for any superscript or subscript thereafter
-    fn single_node(&mut self) -> Node<'a> {
+    fn single_node<K: Sink<'a>>(&mut self, sink: &mut K) -> Result<K::Node, ParseError<'a>> {
+        self.flush()?;
+        let start = self.cur_span.start;
+        let mark = self.spans.len();
+        // Set when the node is passed through from a call that already recorded its span
+        let mut recorded = false;
         let node = match self.cur {
-            Token::Number(number) => Node::Number(number),
-            Token::Letter(x, v) => Node::Letter(x, v),
-            Token::Operator(op) => Node::Operator(op),
-            Token::Function(fun) => Node::Function(fun, None),
-            Token::Space(space) => Node::Space(space),
+            Token::Number(number) => sink.leaf(Node::Number(number)),
+            Token::Letter(x, v) => sink.leaf(Node::Letter(x, v)),
+            Token::Operator(op) => sink.leaf(Node::Operator(op)),
+            Token::Function(fun) => self.operator_name(sink, fun, self.cur_span),
+            Token::Space(space) => sink.leaf(Node::Space(space)),
             Token::Sqrt => {
                 self.next_token();
-                let degree = (self.cur == Token::LSeperator("["))
-                    .then(|| {
-                        let degree = self.parse_group(Token::RSeperator("]"));
-                        self.next_token();
-                        degree
-                    })
-                    .flatten();
-                let content = self.next_node();
-                Node::Sqrt(degree.map(Box::new), Box::new(content))
+                let degree = if self.cur == Token::LSeperator("[") {
+                    let degree = self.parse_group(sink, Token::RSeperator("]"))?;
+                    self.next_token();
+                    degree
+                } else {
+                    None
+                };
+                let content = self.next_arg(sink)?;
+                sink.push(Shape::Sqrt(degree, content))
             }
             Token::Frac => {
                 self.next_token();
-                let numerator = self.next_node().arg();
+                let numerator = self.next_arg(sink)?;
                 self.next_token();
-                let denominator = self.next_node().arg();
-                Node::Frac(numerator, denominator, LineThickness::Medium)
+                let denominator = self.next_arg(sink)?;
+                sink.push(Shape::Frac(numerator, denominator, LineThickness::Medium))
             }
             Token::Binom(display) => {
+                let binom_span = self.cur_span;
+                self.spans.push(binom_span);
                 self.next_token();
-                let numerator = self.next_node();
-                self.next_token();
-                let denominator = self.next_node();
-                let binom = Node::Fenced {
-                    open: Node::StrechedOp(true, "(").into(),
-                    close: Node::StrechedOp(true, ")").into(),
-                    content: Box::new(Node::Frac(
-                        Box::new(numerator),
-                        Box::new(denominator),
-                        LineThickness::Length(0),
-                    )),
-                };
+                let numerator = self.next_argument(sink)?;
+                self.next_token();
+                let denominator = self.next_argument(sink)?;
+                self.record(start);
+                self.spans.push(binom_span);
+                let content = sink.push(Shape::Frac(
+                    numerator,
+                    denominator,
+                    LineThickness::Length(0),
+                ));
+                let open = Node::StrechedOp(true, "(");
+                let binom = sink.fenced(open, content, Node::StrechedOp(true, ")"));
                 match display {
-                    Some(display) => Node::Style(display, Box::new(binom)),
+                    Some(display) => {
+                        self.record(start);
+                        sink.push(Shape::Style(display, binom))
+                    }
                     None => binom,
                 }
             }
             Token::Over(op, acc) => {
                 self.next_token();
-                let target = self.next_node();
-                Node::OverOp(op, acc, Box::new(target))
+                let target = self.next_arg(sink)?;
+                sink.push(Shape::OverOp(op, acc, target))
             }
             Token::Under(op, acc) => {
                 self.next_token();
-                let target = self.next_node();
-                Node::UnderOp(op, acc, Box::new(target))
+                let target = self.next_arg(sink)?;
+                sink.push(Shape::UnderOp(op, acc, target))
             }
             Token::Overset => {
                 self.next_token();
-                let over = self.single_node().arg();
+                let over = self.single_arg(sink)?;
                 self.next_token();
-                let target = self.single_node().arg();
-                Node::Overset { over, target }
+                let target = self.single_arg(sink)?;
+                sink.push(Shape::Overset { over, target })
             }
             Token::Underset => {
                 self.next_token();
-                let under = self.single_node().arg();
+                let under = self.single_arg(sink)?;
                 self.next_token();
-                let target = self.single_node().arg();
-                Node::Underset { under, target }
+                let target = self.single_arg(sink)?;
+                sink.push(Shape::Underset { under, target })
             }
             Token::Overbrace(x) => {
+                let brace_span = self.cur_span;
                 self.next_token();
-                let target = self.single_node().arg();
-                if self.peek == Token::Circumflex {
+                let target = self.single_arg(sink)?;
+                let split = self.spans.len();
+                let over = if self.peek == Token::Circumflex {
                     self.next_token();
                     self.next_token();
-                    let expl = self.single_node().arg();
-                    let over = Node::Overset {
+                    let expl = self.single_arg(sink)?;
+                    self.spans.push(brace_span);
+                    self.record(start);
+                    let brace = sink.leaf(Node::Operator(x));
+                    sink.push(Shape::Overset {
                         over: expl,
-                        target: Box::new(Node::Operator(x)),
-                    };
-                    Node::Overset {
-                        over: Box::new(over),
-                        target,
-                    }
+                        target: brace,
+                    })
                 } else {
-                    Node::Overset {
-                        over: Box::new(Node::Operator(x)),
-                        target,
-                    }
-                }
+                    self.spans.push(brace_span);
+                    sink.leaf(Node::Operator(x))
+                };
+                self.rotate(mark, split);
+                sink.push(Shape::Overset { over, target })
             }
             Token::Underbrace(x) => {
+                let brace_span = self.cur_span;
                 self.next_token();
-                let target = self.single_node().arg();
-                if self.peek == Token::Underscore {
+                let target = self.single_arg(sink)?;
+                let split = self.spans.len();
+                let under = if self.peek == Token::Underscore {
                     self.next_token();
                     self.next_token();
-                    let expl = self.single_node().arg();
-                    let under = Node::Underset {
+                    let expl = self.single_arg(sink)?;
+                    self.spans.push(brace_span);
+                    self.record(start);
+                    let brace = sink.leaf(Node::Operator(x));
+                    sink.push(Shape::Underset {
                         under: expl,
-                        target: Box::new(Node::Operator(x)),
-                    };
-                    Node::Underset {
-                        under: Box::new(under),
-                        target,
-                    }
+                        target: brace,
+                    })
                 } else {
-                    Node::Underset {
-                        under: Box::new(Node::Operator(x)),
-                        target,
-                    }
-                }
+                    self.spans.push(brace_span);
+                    sink.leaf(Node::Operator(x))
+                };
+                self.rotate(mark, split);
+                sink.push(Shape::Underset { under, target })
             }
-            Token::BigOp(op) => match self.peek {
-                Token::Underscore => {
-                    self.next_token();
-                    self.next_token();
-                    let under = self.single_node().arg();
-                    if self.peek == Token::Circumflex {
+            Token::BigOp(op) => {
+                let op_span = self.cur_span;
+                match self.peek {
+                    Token::Underscore => {
                         self.next_token();
                         self.next_token();
-                        let over = self.single_node().arg();
-                        Node::UnderOver {
-                            target: Box::new(Node::Operator(op)),
-                            under,
-                            over,
+                        let under = self.single_arg(sink)?;
+                        if self.peek == Token::Circumflex {
+                            self.next_token();
+                            self.next_token();
+                            let over = self.single_arg(sink)?;
+                            self.spans.insert(mark, op_span);
+                            let target = sink.leaf(Node::Operator(op));
+                            sink.push(Shape::UnderOver {
+                                target,
+                                under,
+                                over,
+                            })
+                        } else {
+                            self.spans.push(op_span);
+                            let target = sink.leaf(Node::Operator(op));
+                            sink.push(Shape::Underset { under, target })
                         }
-                    } else {
-                        Node::Underset{ target: Box::new(Node::Operator(op)), under }
                     }
-                }
-                Token::Circumflex => {
-                    self.next_token();
-                    self.next_token();
-                    let over = self.single_node().arg();
-                    if self.peek == Token::Underscore {
+                    Token::Circumflex => {
                         self.next_token();
                         self.next_token();
-                        let under = self.single_node().arg();
-                        Node::UnderOver {
-                            target: Box::new(Node::Operator(op)),
-                            under,
-                            over,
+                        let over = self.single_arg(sink)?;
+                        if self.peek == Token::Underscore {
+                            self.next_token();
+                            self.next_token();
+                            let split = self.spans.len();
+                            let under = self.single_arg(sink)?;
+                            self.rotate(mark, split);
+                            self.spans.insert(mark, op_span);
+                            let target = sink.leaf(Node::Operator(op));
+                            sink.push(Shape::UnderOver {
+                                target,
+                                under,
+                                over,
+                            })
+                        } else {
+                            sink.push(Shape::OverOp(op, Accent::False, over))
                         }
-                    } else {
-                        Node::OverOp(op, Accent::False, over)
                     }
+                    _ => sink.leaf(Node::Operator(op)),
                 }
-                _ => Node::Operator(op),
-            },
+            }
             Token::Lim(lim) => {
-                let lim = Node::Function(lim, None);
-                if self.peek == Token::Underscore {
-                    self.next_token();
-                    self.next_token();
-                    let under = self.single_node().arg();
-                    Node::Underset{ target: Box::new(lim), under }
-                } else {
-                    lim
-                }
+                let target = self.operator_name(sink, lim, self.cur_span);
+                self.limits(sink, target, self.cur_span, mark)?
             }
             Token::Slashed => {
                 self.next_token();
                 // self.next_token();
-                let node = self.single_node().arg();
+                let node = self.single_arg(sink)?;
                 // self.next_token();
-                Node::Slashed(node)
+                sink.push(Shape::Slashed(node))
+            }
+            Token::Atom(class) => {
+                self.next_token();
+                let node = self.single_arg(sink)?;
+                sink.push(Shape::Atom(class, node))
             }
             Token::Style(var) => {
                 self.next_token();
                 // self.next_token();
-                let node = self.single_node();
-                set_variant(node, var)
+                let node = self.single_arg(sink)?;
+                recorded = true;
+                sink.set_variant(node, var)
             }
-            Token::Integral(int) => match self.peek {
-                Token::Underscore => {
-                    self.next_token();
-                    self.next_token();
-                    let sub = self.single_node().arg();
-                    if self.peek == Token::Circumflex {
+            Token::Integral(int) => {
+                let int_span = self.cur_span;
+                match self.peek {
+                    Token::Underscore => {
                         self.next_token();
                         self.next_token();
-                        let sup = self.single_node().arg();
-                        Node::SubSup {
-                            target: Box::new(Node::Operator(int)),
-                            sub,
-                            sup,
+                        let sub = self.single_arg(sink)?;
+                        if self.peek == Token::Circumflex {
+                            self.next_token();
+                            self.next_token();
+                            let sup = self.single_arg(sink)?;
+                            self.spans.insert(mark, int_span);
+                            let target = sink.leaf(Node::Operator(int));
+                            sink.push(Shape::SubSup { target, sub, sup })
+                        } else {
+                            self.spans.insert(mark, int_span);
+                            let target = sink.leaf(Node::Operator(int));
+                            sink.push(Shape::Subscript(target, sub))
                         }
-                    } else {
-                        Node::Subscript(Box::new(Node::Operator(int)), sub)
                     }
-                }
-                Token::Circumflex => {
-                    self.next_token();
-                    self.next_token();
-                    let sup = self.single_node().arg();
-                    if self.peek == Token::Underscore {
+                    Token::Circumflex => {
                         self.next_token();
                         self.next_token();
-                        let sub = self.single_node().arg();
-                        Node::SubSup {
-                            target: Box::new(Node::Operator(int)),
-                            sub,
-                            sup,
+                        let sup = self.single_arg(sink)?;
+                        if self.peek == Token::Underscore {
+                            self.next_token();
+                            self.next_token();
+                            let split = self.spans.len();
+                            let sub = self.single_arg(sink)?;
+                            self.rotate(mark, split);
+                            self.spans.insert(mark, int_span);
+                            let target = sink.leaf(Node::Operator(int));
+                            sink.push(Shape::SubSup { target, sub, sup })
+                        } else {
+                            self.spans.insert(mark, int_span);
+                            let target = sink.leaf(Node::Operator(int));
+                            sink.push(Shape::Superscript(target, sup))
                         }
-                    } else {
-                        Node::Superscript(Box::new(Node::Operator(int)), sup)
                     }
+                    _ => sink.leaf(Node::Operator(int)),
                 }
-                _ => Node::Operator(int),
-            },
-            Token::LSeperator(open) => (|| {
-                let token = match open {
-                    "(" => ")",
-                    "[" => "]",
-                    "{" => "}",
-                    "⌈" => "⌉",
-                    "⌜" => "⌝",
-                    "⌞" => "⌟",
-                    "⌊" => "⌋",
-                    "⦗" => "⦘",
-                    "⟦" => "⟧",
-                    "|" => "|",
-                    open => unreachable!("Invalid open delimiter: {open}"),
-                };
-                let Some(content) = self.parse_group(Token::RSeperator(token)) else {
-                    return Node::Operator(open);
+            }
+            Token::Paren("{") => {
+                // Unlike a group, the braces of a set are kept and do not stretch
+                self.spans.push(self.cur_span);
+                let content = match self.parse_group(sink, Token::Paren("}"))? {
+                    Some(content) => content,
+                    None => {
+                        self.spans
+                            .push(Span::new(self.cur_span.start, self.cur_span.start));
+                        sink.push(Shape::Row(Vec::new()))
+                    }
                 };
-                let close = Node::StrechedOp(true, token).into();
-                Node::Fenced {
-                    open: Node::StrechedOp(true, open).into(),
-                    close,
-                    content: Box::new(content),
+                self.record(self.cur_span.start);
+                sink.fenced(
+                    Node::StrechedOp(false, "{"),
+                    content,
+                    Node::StrechedOp(false, "}"),
+                )
+            }
+            Token::LSeperator(open) => {
+                let token = closing(open);
+                self.spans.push(self.cur_span);
+                match token {
+                    Some(token) => match self.parse_group(sink, Token::RSeperator(token))? {
+                        Some(content) => {
+                            self.record(self.cur_span.start);
+                            sink.fenced(
+                                Node::StrechedOp(true, open),
+                                content,
+                                Node::StrechedOp(true, token),
+                            )
+                        }
+                        None => {
+                            self.spans.truncate(mark);
+                            sink.leaf(Node::Operator(open))
+                        }
+                    },
+                    None => {
+                        self.spans.truncate(mark);
+                        self.fail(
+                            self.error(ParseErrorKind::InvalidDelimiter, None),
+                            Recovery::Substituted,
+                        )?;
+                        sink.leaf(Node::Operator(open))
+                    }
                 }
-            })(),
+            }
             Token::Left => {
                 self.next_token();
-                let mut s = None;
-                let open = match self.cur {
-                    Token::LSeperator(op) => op,
-                    Token::Big(size) => {
-                        self.next_token();
-                        s = Some(size);
-                        match self.cur {
-                            Token::Paren(paren)
-                            | Token::LSeperator(paren)
-                            | Token::RSeperator(paren) => paren,
-                            token => todo!("Invalid token: {token:?}"),
-                        }
+                let (s, open) = self.delimiter()?;
+                self.record(start);
+                let content = self.parse_group(sink, Token::Right)?;
+                let content = match content {
+                    Some(content) => content,
+                    None => {
+                        self.spans
+                            .push(Span::new(self.cur_span.start, self.cur_span.start));
+                        sink.push(Shape::Row(Vec::new()))
                     }
-                    token => todo!("Invalid token: {token:?}"),
                 };
-                // self.next_token();
-                // let Some(content) = self.parse_group(Token::Right) else {
-                //   return open;
-                // };
-                let content = self.parse_group(Token::Right).unwrap(); // TODO
-                self.next_token();
-                let mut s2 = None;
-                let close = match self.cur {
-                    Token::RSeperator(op) | Token::Paren(op) => op,
-                    Token::Big(size) => {
-                        self.next_token();
-                        s2 = Some(size);
-                        match self.cur {
-                            Token::Paren(paren)
-                            | Token::LSeperator(paren)
-                            | Token::RSeperator(paren) => paren,
-                            token => todo!("Invalid token: {token:?}"),
-                        }
-                    }
-                    token => todo!("Invalid token: {token:?}"),
+                let right = self.cur_span.start;
+                // A group left open at the end of the input gets an empty closing delimiter
+                let (s2, close) = if self.cur == Token::EOF {
+                    (None, "")
+                } else {
+                    self.next_token();
+                    self.delimiter()?
                 };
+                self.record(right);
 
                 let open = match s {
                     Some(size) => Node::SizedParen { size, paren: open },
@@ -416,53 +1148,86 @@ impl<'a> Parser<'a> {
                     None => Node::StrechedOp(true, close),
                 };
 
-                Node::Fenced {
-                    open: Box::new(open),
-                    close: Box::new(close),
-                    content: Box::new(content),
+                sink.fenced(open, content, close)
+            }
+            Token::Paren("|") => {
+                self.spans.push(self.cur_span);
+                match self.parse_group(sink, Token::Paren("|"))? {
+                    Some(content) => {
+                        self.record(self.cur_span.start);
+                        sink.fenced(
+                            Node::StrechedOp(true, "|"),
+                            content,
+                            Node::StrechedOp(true, "|"),
+                        )
+                    }
+                    None => {
+                        self.spans.truncate(mark);
+                        sink.leaf(Node::Operator("|"))
+                    }
                 }
             }
-            Token::Paren("|") => self
-                .parse_group(Token::Paren("|"))
-                .unwrap_or(Node::Operator("|")),
             Token::Middle => {
                 let stretchy = true;
                 self.next_token();
-                match self.single_node() {
-                    Node::Operator(op) => Node::StrechedOp(stretchy, op),
-                    Node::OtherOperator(op) => Node::StrechedOp(stretchy, op),
-                    _ => unimplemented!(),
+                match self.cur {
+                    Token::Operator(op)
+                    | Token::Paren(op)
+                    | Token::LSeperator(op)
+                    | Token::RSeperator(op) => sink.leaf(Node::StrechedOp(stretchy, op)),
+                    token => {
+                        self.fail(
+                            self.error(ParseErrorKind::InvalidDelimiter, None),
+                            Recovery::Substituted,
+                        )?;
+                        sink.leaf(Node::Undefined(token))
+                    }
                 }
             }
             Token::Big(size) => {
                 self.next_token();
                 match self.cur {
                     Token::Paren(paren) | Token::LSeperator(paren) | Token::RSeperator(paren) => {
-                        Node::SizedParen { size, paren }
+                        sink.leaf(Node::SizedParen { size, paren })
                     }
-                    token => Node::Undefined(token),
+                    token => sink.leaf(Node::Undefined(token)),
                 }
             }
             Token::Begin => {
                 self.next_token();
-                let environment = self.parse_text(Token::RSeperator("}"));
+                let begin = self.parse_text(Token::RSeperator("}"))?;
                 // let environment = self.single_node().arg(self);
-                let (columnalign, environment) = if environment.starts_with("align") {
-                    (ColumnAlign::Left, "matrix")
-                } else {
-                    (ColumnAlign::Center, environment)
-                };
+                let aligned = begin.starts_with("align");
+                let environment = if aligned { "matrix" } else { begin };
+                let open_span = Span::new(start, self.cur_span.end);
 
-                // Do we check here if the environment is the same?
-                let Some(content) = self.parse_group(Token::End) else {
-                    return Node::Text(environment, Variant::Normal);
-                };
-                self.next_token();
-                let _end_environment = self.parse_text(Token::RSeperator("}")); // TODO check if it's the same as the start
-                let content = Node::Matrix(Box::new(content), columnalign);
-                let matrix = match environment {
+                let mut grid = Grid::default();
+                if environment == "array" {
+                    self.column_spec(sink, &mut grid)?;
+                }
+                self.parse_grid(sink, &mut grid)?;
+                if environment != "array" {
+                    // Aligned columns pair a right aligned one with a left aligned one
+                    grid.align(|column| match column % 2 {
+                        0 if aligned => ColumnAlign::Right,
+                        _ if aligned => ColumnAlign::Left,
+                        _ => ColumnAlign::Center,
+                    });
+                }
+                let close_start = self.cur_span.start;
+                if self.cur != Token::EOF {
+                    self.next_token();
+                    let end = self.parse_text(Token::RSeperator("}"))?;
+                    if end != begin {
+                        self.fail(
+                            self.error(ParseErrorKind::MismatchedEnvironment { begin, end }, None),
+                            Recovery::ClosedGroup,
+                        )?;
+                    }
+                }
+                match environment {
                     // TODO Add more environments, they are not all matrices
-                    "matrix" => content,
+                    "matrix" | "array" => sink.push(Shape::Matrix(grid)),
                     "pmatrix" | "bmatrix" | "vmatrix" | "Bmatrix" | "Vmatrix" => {
                         let (open, close) = match environment {
                             "pmatrix" => ("(", ")"),
@@ -472,44 +1237,110 @@ impl<'a> Parser<'a> {
                             "Vmatrix" => ("║", "║"),
                             _ => unreachable!(),
                         };
-                        Node::Fenced {
-                            open: Box::new(Node::StrechedOp(true, open)),
-                            close: Box::new(Node::StrechedOp(true, close)),
-                            content: Box::new(content),
-                        }
+                        self.record(start);
+                        self.spans.insert(mark, open_span);
+                        self.record(close_start);
+                        let content = sink.push(Shape::Matrix(grid));
+                        sink.fenced(
+                            Node::StrechedOp(true, open),
+                            content,
+                            Node::StrechedOp(true, close),
+                        )
                     }
-                    environment => Node::Text(environment, Variant::Normal),
-                };
-
-                matrix
+                    environment => {
+                        self.spans.truncate(mark);
+                        sink.leaf(Node::Text(environment, Variant::Normal))
+                    }
+                }
+            }
+            Token::OperatorName if self.peek == Token::Operator("*") => {
+                self.next_token();
+                self.next_token();
+                let content = self.parse_text(Token::RSeperator("}"))?;
+                let span = Span::new(start, self.cur_span.end);
+                let target = self.operator_name(sink, content, span);
+                self.limits(sink, target, span, mark)?
             }
             Token::Package | Token::OperatorName | Token::Text | Token::Title => {
                 let c = self.cur;
                 self.next_token();
-                let content = self.parse_text(Token::RSeperator("}"));
+                let content = self.parse_text(Token::RSeperator("}"))?;
                 match c {
-                    Token::Package => Node::Package(content),
-                    Token::OperatorName => Node::Function(content, None),
-                    Token::Text => Node::Text(content, Variant::Normal),
-                    Token::Title => Node::Title(content),
+                    Token::Package => sink.leaf(Node::Package(content)),
+                    Token::OperatorName => {
+                        self.operator_name(sink, content, Span::new(start, self.cur_span.end))
+                    }
+                    Token::Text => sink.leaf(Node::Text(content, Variant::Normal)),
+                    Token::Title => sink.leaf(Node::Title(content)),
                     _ => unreachable!(),
                 }
             }
-            Token::Ampersand => Node::Ampersand,
-            Token::NewLine => Node::NewLine,
-            token => Node::Undefined(token),
+            Token::Command(command) => {
+                self.fail(
+                    self.error(ParseErrorKind::UnknownCommand(command), None),
+                    Recovery::Substituted,
+                )?;
+                sink.leaf(Node::Undefined(self.cur))
+            }
+            Token::Right | Token::End => {
+                self.fail(
+                    self.error(ParseErrorKind::UnexpectedToken, None),
+                    Recovery::Substituted,
+                )?;
+                sink.leaf(Node::Undefined(self.cur))
+            }
+            Token::Ampersand => sink.leaf(Node::Ampersand),
+            Token::NewLine => sink.leaf(Node::NewLine),
+            token => sink.leaf(Node::Undefined(token)),
         };
 
-        match self.peek {
+        if recorded {
+            let end = self.cur_span.end;
+            if let Some(span) = self.spans.last_mut() {
+                *span = Span::new(start, end);
+            }
+        } else {
+            self.record(start);
+        }
+
+        Ok(match self.peek {
             Token::Operator("\'") => {
                 self.next_token();
-                Node::Superscript(Box::new(node), Box::new(Node::Operator("′")))
+                self.record(self.cur_span.start);
+                self.record(start);
+                let prime = sink.leaf(Node::Operator("′"));
+                sink.push(Shape::Superscript(node, prime))
             }
             _ => node,
-        }
+        })
     }
 }
 
+/// The delimiter closing a group opened by `open`
+pub(crate) fn closing(open: &str) -> Option<&'static str> {
+    Some(match open {
+        "(" => ")",
+        "[" => "]",
+        "{" => "}",
+        "⌈" => "⌉",
+        "⌜" => "⌝",
+        "⌞" => "⌟",
+        "⌊" => "⌋",
+        "⦗" => "⦘",
+        "⟮" => "⟯",
+        "⟦" => "⟧",
+        "|" => "|",
+        "⟨" => "⟩",
+        _ => return None,
+    })
+}
+
+const RECOVERING: &str = "a recovering parser never returns an error";
+
+/// Most columns, rules and separator nodes a column specification may hold, which bounds the
+/// memory taken by a `*{count}{spec}` repetition
+const MAX_COLUMNS: usize = 4096;
+
 /// Recursively sets all the letters to the given Variant
 fn set_variant(node: Node, var: Variant) -> Node {
     match node {
@@ -518,3 +1349,139 @@ fn set_variant(node: Node, var: Variant) -> Node {
         node => node,
     }
 }
+
+/// A node being built by the parser, its children already handed over to a [`Sink`]
+pub(crate) enum Shape<'a, N> {
+    Leaf(Node<'a>),
+    Subscript(N, N),
+    Superscript(N, N),
+    SubSup { target: N, sub: N, sup: N },
+    OverOp(&'a str, Accent, N),
+    UnderOp(&'a str, Accent, N),
+    Overset { over: N, target: N },
+    Underset { under: N, target: N },
+    UnderOver { target: N, under: N, over: N },
+    Sqrt(Option<N>, N),
+    Frac(N, N, LineThickness),
+    Row(Vec<N>),
+    Fenced { open: N, close: N, content: N },
+    Matrix(Grid<N>),
+    Slashed(N),
+    Style(DisplayStyle, N),
+    Atom(AtomClass, N),
+}
+
+/// Where the parser puts the nodes it builds, either boxing them or pushing them in an arena
+pub(crate) trait Sink<'a> {
+    /// Handle to a built node, cloned when a node is used at several places
+    type Node: Clone;
+
+    /// Builds `shape`, taking over its children
+    fn push(&mut self, shape: Shape<'a, Self::Node>) -> Self::Node;
+
+    fn leaf(&mut self, node: Node<'a>) -> Self::Node {
+        self.push(Shape::Leaf(node))
+    }
+
+    /// Builds `content` fenced by `open` and `close`, the delimiters being built after it
+    fn fenced(&mut self, open: Node<'a>, content: Self::Node, close: Node<'a>) -> Self::Node {
+        let open = self.leaf(open);
+        let close = self.leaf(close);
+        self.push(Shape::Fenced {
+            open,
+            close,
+            content,
+        })
+    }
+
+    /// Returns the content of a `{..}` group, or the node itself when it is not one
+    fn unwrap_group(&mut self, node: Self::Node) -> Result<Self::Node, Self::Node>;
+
+    /// Recursively sets all the letters of `node` to the given Variant
+    fn set_variant(&mut self, node: Self::Node, var: Variant) -> Self::Node;
+
+    fn is_empty_row(&self, node: &Self::Node) -> bool;
+
+    /// Drops a node that was built but is not used
+    fn discard(&mut self, node: Self::Node);
+}
+
+/// Builds the boxed [`Node`] trees returned by [`Parser::parse`]
+pub(crate) struct Boxed;
+
+impl<'a> Sink<'a> for Boxed {
+    type Node = Node<'a>;
+
+    fn push(&mut self, shape: Shape<'a, Node<'a>>) -> Node<'a> {
+        match shape {
+            Shape::Leaf(node) => node,
+            Shape::Subscript(target, script) => Node::Subscript(target.into(), script.into()),
+            Shape::Superscript(target, script) => Node::Superscript(target.into(), script.into()),
+            Shape::SubSup { target, sub, sup } => Node::SubSup {
+                target: target.into(),
+                sub: sub.into(),
+                sup: sup.into(),
+            },
+            Shape::OverOp(op, acc, target) => Node::OverOp(op, acc, target.into()),
+            Shape::UnderOp(op, acc, target) => Node::UnderOp(op, acc, target.into()),
+            Shape::Overset { over, target } => Node::Overset {
+                over: over.into(),
+                target: target.into(),
+            },
+            Shape::Underset { under, target } => Node::Underset {
+                under: under.into(),
+                target: target.into(),
+            },
+            Shape::UnderOver {
+                target,
+                under,
+                over,
+            } => Node::UnderOver {
+                target: target.into(),
+                under: under.into(),
+                over: over.into(),
+            },
+            Shape::Sqrt(degree, content) => Node::Sqrt(degree.map(Box::new), content.into()),
+            Shape::Frac(numerator, denominator, thickness) => {
+                Node::Frac(numerator.into(), denominator.into(), thickness)
+            }
+            Shape::Row(nodes) => Node::Row(nodes),
+            Shape::Fenced {
+                open,
+                close,
+                content,
+            } => Node::Fenced {
+                open: open.into(),
+                close: close.into(),
+                content: content.into(),
+            },
+            Shape::Matrix(grid) => Node::Matrix(grid.into()),
+            Shape::Slashed(node) => Node::Slashed(node.into()),
+            Shape::Style(style, node) => Node::Style(style, node.into()),
+            Shape::Atom(class, node) => Node::Atom(class, node.into()),
+        }
+    }
+
+    fn unwrap_group(&mut self, node: Node<'a>) -> Result<Node<'a>, Node<'a>> {
+        match node {
+            Node::Fenced {
+                open,
+                close,
+                content,
+            } if *open == Node::StrechedOp(true, "{") && *close == Node::StrechedOp(true, "}") => {
+                Ok(*content)
+            }
+            node => Err(node),
+        }
+    }
+
+    fn set_variant(&mut self, node: Node<'a>, var: Variant) -> Node<'a> {
+        set_variant(node, var)
+    }
+
+    fn is_empty_row(&self, node: &Node<'a>) -> bool {
+        matches!(node, Node::Row(nodes) if nodes.is_empty())
+    }
+
+    fn discard(&mut self, _: Node<'a>) {}
+}
@@ -0,0 +1,104 @@
+use crate::{
+    lexer::Lexer,
+    macros::{read_definition, MacroTable},
+    token::Token,
+};
+use alloc::vec::Vec;
+
+/// Macros and metadata read from the preamble of a LaTeX document
+///
+/// The macros can be handed to [`Parser::with_macros`](crate::Parser::with_macros) to parse
+/// the formulas of the document with the definitions of its author.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Preamble<'a> {
+    /// Macros from `\newcommand`, `\renewcommand`, `\def`, `\let` and `\DeclareMathOperator`
+    pub macros: MacroTable,
+    /// Packages loaded by `\usepackage`, in order
+    pub packages: Vec<Package<'a>>,
+    pub title: Option<&'a str>,
+    pub author: Option<&'a str>,
+}
+
+/// A package loaded by `\usepackage[options]{name}`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Package<'a> {
+    pub name: &'a str,
+    pub options: Vec<&'a str>,
+}
+
+impl<'a> Preamble<'a> {
+    /// Scans `document` up to `\begin{document}`, or all of it if it has none
+    pub fn scan(document: &'a str) -> Self {
+        let mut preamble = Self::default();
+        let mut lexer = Lexer::new(document);
+        loop {
+            let token = lexer.next_spanned();
+            let command = document[token.span.start..token.span.end].strip_prefix('\\');
+            match token.value {
+                Token::EOF => break,
+                Token::Begin => {
+                    let environment = lexer.read_argument();
+                    if environment.is_some_and(|group| group.text.trim() == "document") {
+                        break;
+                    }
+                }
+                Token::NewCommand
+                | Token::RenewCommand
+                | Token::Def
+                | Token::DeclareMathOperator => {
+                    let Ok((name, mac)) = read_definition(&mut lexer, token.value) else {
+                        continue;
+                    };
+                    let redefines =
+                        matches!(token.value, Token::NewCommand | Token::DeclareMathOperator);
+                    if !redefines || !preamble.macros.contains(name) {
+                        preamble.macros.insert(name, mac);
+                    }
+                }
+                Token::Package => {
+                    let options = lexer
+                        .read_delimited('[', ']')
+                        .map_or_else(Vec::new, |group| list(group.text));
+                    if let Some(names) = lexer.read_argument() {
+                        preamble
+                            .packages
+                            .extend(list(names.text).into_iter().map(|name| Package {
+                                name,
+                                options: options.clone(),
+                            }));
+                    }
+                }
+                Token::Title => {
+                    preamble.title = lexer.read_argument().map(|group| group.text.trim())
+                }
+                _ => match command {
+                    Some("author") => {
+                        preamble.author = lexer.read_argument().map(|group| group.text.trim());
+                    }
+                    Some("let") => {
+                        let name = lexer.read_argument();
+                        lexer.skip_whitespace();
+                        if lexer.cur == '=' {
+                            lexer.read_char();
+                        }
+                        if let (Some(name), Some(target)) = (name, lexer.read_argument()) {
+                            preamble.macros.define(name.text, 0, target.text);
+                        }
+                    }
+                    _ => {}
+                },
+            }
+        }
+        preamble
+    }
+}
+
+/// Splits a comma separated list, leaving out empty items and `%` comments
+fn list(text: &str) -> Vec<&str> {
+    text.lines()
+        .map(|line| line.find('%').map_or(line, |i| &line[..i]))
+        .flat_map(|line| line.split(','))
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .collect()
+}
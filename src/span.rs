@@ -0,0 +1,138 @@
+use crate::models::Node;
+use alloc::vec::Vec;
+
+/// Byte range of the input a token or node was read from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Self {
+        Self { start, end }
+    }
+
+    pub fn len(&self) -> usize {
+        self.end - self.start
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+
+    /// The smallest span covering both `self` and `other`
+    pub fn to(self, other: Span) -> Span {
+        Span::new(self.start.min(other.start), self.end.max(other.end))
+    }
+}
+
+impl From<Span> for core::ops::Range<usize> {
+    fn from(span: Span) -> Self {
+        span.start..span.end
+    }
+}
+
+/// Line and column of a byte offset in the input, all counted from 0
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Position {
+    pub line: usize,
+    /// Column in bytes
+    pub column: usize,
+    /// Column in UTF-16 code units, as used by the Language Server Protocol and JavaScript
+    pub utf16_column: usize,
+}
+
+impl Position {
+    /// Position of the byte `offset` of `input`, which is clamped to the length of `input`
+    pub fn from_offset(input: &str, offset: usize) -> Self {
+        let offset = offset.min(input.len());
+        let before = &input.as_bytes()[..offset];
+        let line_start = before
+            .iter()
+            .rposition(|&b| b == b'\n')
+            .map_or(0, |i| i + 1);
+        let utf16_column = input[line_start..]
+            .char_indices()
+            .take_while(|(i, _)| line_start + i < offset)
+            .map(|(_, c)| c.len_utf16())
+            .sum();
+        Self {
+            line: before.iter().filter(|&&b| b == b'\n').count(),
+            column: offset - line_start,
+            utf16_column,
+        }
+    }
+
+    /// Position right after `text` when it starts at this one
+    pub(crate) fn advance(mut self, text: &[u8]) -> Self {
+        for &byte in text {
+            match byte {
+                b'\n' => {
+                    self.line += 1;
+                    self.column = 0;
+                    self.utf16_column = 0;
+                }
+                // Continuation bytes of a character
+                0x80..=0xBF => self.column += 1,
+                // The first byte of a four byte character, which is two UTF-16 code units
+                0xF0.. => {
+                    self.column += 1;
+                    self.utf16_column += 2;
+                }
+                _ => {
+                    self.column += 1;
+                    self.utf16_column += 1;
+                }
+            }
+        }
+        self
+    }
+}
+
+/// A value together with the span of input it was read from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Spanned<T> {
+    pub value: T,
+    pub span: Span,
+}
+
+impl<T> Spanned<T> {
+    pub fn new(value: T, span: Span) -> Self {
+        Self { value, span }
+    }
+}
+
+/// Spans of every node in a parsed tree, stored in post-order
+///
+/// The order is the one of [`Node::post_order`] over the top level nodes, so the table
+/// can be walked alongside the tree without storing spans in the nodes themselves.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SpanTable {
+    pub(crate) spans: Vec<Span>,
+}
+
+impl SpanTable {
+    pub fn as_slice(&self) -> &[Span] {
+        &self.spans
+    }
+
+    /// Pairs every node of `nodes`, in post-order, with its span
+    pub fn iter<'n, 'a>(
+        &'n self,
+        nodes: &'n [Node<'a>],
+    ) -> impl Iterator<Item = Spanned<&'n Node<'a>>> + 'n {
+        nodes
+            .iter()
+            .flat_map(Node::post_order)
+            .zip(self.spans.iter())
+            .map(|(node, span)| Spanned::new(node, *span))
+    }
+
+    /// Looks up the span of `node`, which must be a reference into `nodes`
+    pub fn span_of(&self, nodes: &[Node], node: &Node) -> Option<Span> {
+        self.iter(nodes)
+            .find(|spanned| core::ptr::eq(spanned.value, node))
+            .map(|spanned| spanned.span)
+    }
+}
@@ -0,0 +1,111 @@
+use crate::{
+    models::{Accent, AtomClass, DisplayStyle, Space, Variant},
+    token::Token,
+};
+
+/// Index in [`SYMBOLS`] of the command `name`
+fn command_index(name: &str) -> Option<u16> {
+    include!(concat!(env!("OUT_DIR"), "/commands.rs"))
+}
+
+/// Atom class of every glyph, the one of its canonical command
+static CLASSES: phf::Map<&'static str, AtomClass> =
+    include!(concat!(env!("OUT_DIR"), "/classes.rs"));
+
+/// What a command of the registry stands for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SymbolClass {
+    /// An ordinary symbol, such as `\alpha` or `\infty`
+    Letter,
+    /// A binary operator or relation, such as `\times` or `\leq`
+    Operator,
+    /// A large operator taking limits, such as `\sum` or `\int`
+    BigOp,
+    /// A named function, such as `\sin` or `\lim`
+    Function,
+    /// An accent or brace over its argument, such as `\hat` or `\overbrace`
+    Over,
+    /// An accent or brace under its argument, such as `\underline` or `\underbrace`
+    Under,
+    /// A delimiter, such as `\langle` or `\|`
+    Delimiter,
+    Space,
+    /// A delimiter size, such as `\big`
+    Size,
+    /// A style applied to its argument, such as `\mathbf`
+    Style,
+    /// A command giving structure to the formula, such as `\frac` or `\begin`
+    Structure,
+}
+
+/// A command known to the lexer
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Symbol {
+    /// Name of the command, without its leading `\`
+    pub command: &'static str,
+    /// Token the command is lexed as
+    pub token: Token<'static>,
+    /// Package providing the command, `None` for the ones built into LaTeX
+    pub package: Option<&'static str>,
+    /// Human readable name, such as "less-than or equal to"
+    pub name: &'static str,
+}
+
+impl Symbol {
+    const fn new(
+        command: &'static str,
+        token: Token<'static>,
+        package: Option<&'static str>,
+        name: &'static str,
+    ) -> Self {
+        Self {
+            command,
+            token,
+            package,
+            name,
+        }
+    }
+
+    /// Looks up the command `name`, given without its leading `\`
+    pub fn lookup(name: &str) -> Option<&'static Symbol> {
+        command_index(name).map(|index| &SYMBOLS[usize::from(index)])
+    }
+
+    /// Glyph the command produces, or the name of a function, `None` for commands without one
+    pub fn glyph(&self) -> Option<&'static str> {
+        self.token.glyph()
+    }
+
+    pub fn class(&self) -> SymbolClass {
+        match self.token {
+            Token::Letter(..) => SymbolClass::Letter,
+            Token::Operator(_) => SymbolClass::Operator,
+            Token::BigOp(_) | Token::Integral(_) => SymbolClass::BigOp,
+            Token::Function(_) | Token::Lim(_) => SymbolClass::Function,
+            Token::Over(..) | Token::Overbrace(_) => SymbolClass::Over,
+            Token::Under(..) | Token::Underbrace(_) => SymbolClass::Under,
+            Token::Paren(_) | Token::LSeperator(_) | Token::RSeperator(_) => SymbolClass::Delimiter,
+            Token::Space(_) => SymbolClass::Space,
+            Token::Big(_) => SymbolClass::Size,
+            Token::Style(_) => SymbolClass::Style,
+            _ => SymbolClass::Structure,
+        }
+    }
+}
+
+/// Every command known to the lexer, the canonical command of a glyph comes first
+pub static SYMBOLS: &[Symbol] = &include!(concat!(env!("OUT_DIR"), "/symbols.rs"));
+
+/// Atom class of a glyph, whether it comes from a command or is typed as is
+pub(crate) fn glyph_class(glyph: &str) -> Option<AtomClass> {
+    let class = match glyph {
+        "+" | "-" | "*" => AtomClass::Bin,
+        "=" | "<" | ">" | ":" | ":=" => AtomClass::Rel,
+        "," | ";" => AtomClass::Punct,
+        "(" | "[" => AtomClass::Open,
+        ")" | "]" | "!" => AtomClass::Close,
+        "." | "/" | "\'" | "|" => AtomClass::Ord,
+        glyph => return CLASSES.get(glyph).copied(),
+    };
+    Some(class)
+}
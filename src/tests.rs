@@ -237,7 +237,10 @@ fn lexer_test_runs() {
                 Token::Letter("y", Variant::Italic),
             ],
         ),
-        (r"\ 1", vec![Token::Space(1.), Token::Number("1")]),
+        (
+            r"\ 1",
+            vec![Token::Space(Space::from_mu(18)), Token::Number("1")],
+        ),
     ];
     test_lexer(problems);
 }
@@ -442,3 +445,1477 @@ fn test_replace_latex_block() {
         ]))]
     );
 }
+
+fn test_parse_error(input: &str, kind: ParseErrorKind, expected: Option<Token>, found: Token) {
+    let err = Parser::new(input).try_parse().unwrap_err();
+    assert_eq!(err.kind, kind);
+    assert_eq!(err.expected, expected);
+    assert_eq!(err.found, found);
+}
+
+#[test]
+fn test_try_parse_ok() {
+    assert_eq!(
+        Parser::new(r"\left(\right)").try_parse(),
+        Ok(vec![Node::Fenced {
+            open: Node::StrechedOp(true, "(").into(),
+            close: Node::StrechedOp(true, ")").into(),
+            content: Node::Row(vec![]).into(),
+        }])
+    );
+    assert_eq!(
+        Parser::new(r"\langle x \rangle").try_parse(),
+        Ok(vec![Node::Fenced {
+            open: Node::StrechedOp(true, "⟨").into(),
+            close: Node::StrechedOp(true, "⟩").into(),
+            content: Node::Letter("x", Variant::Italic).into(),
+        }])
+    );
+    assert_eq!(
+        Parser::new(r"\left( x \right.").try_parse(),
+        Ok(vec![Node::Fenced {
+            open: Node::StrechedOp(true, "(").into(),
+            close: Node::StrechedOp(true, "").into(),
+            content: Node::Letter("x", Variant::Italic).into(),
+        }])
+    );
+    assert_eq!(
+        Parser::new(r"\left. f \right|_0").try_parse(),
+        Ok(vec![Node::Subscript(
+            Node::Fenced {
+                open: Node::StrechedOp(true, "").into(),
+                close: Node::StrechedOp(true, "|").into(),
+                content: Node::Letter("f", Variant::Italic).into(),
+            }
+            .into(),
+            Node::Number("0").into(),
+        )])
+    );
+}
+
+#[test]
+fn test_try_parse_errors() {
+    test_parse_error(
+        r"\left( x + 1",
+        ParseErrorKind::UnterminatedGroup,
+        Some(Token::Right),
+        Token::EOF,
+    );
+    test_parse_error(
+        r"\left x \right)",
+        ParseErrorKind::InvalidDelimiter,
+        None,
+        Token::Letter("x", Variant::Italic),
+    );
+    test_parse_error(
+        r"\left( x \right y",
+        ParseErrorKind::InvalidDelimiter,
+        None,
+        Token::Letter("y", Variant::Italic),
+    );
+    test_parse_error(
+        r"a \middle x",
+        ParseErrorKind::InvalidDelimiter,
+        None,
+        Token::Letter("x", Variant::Italic),
+    );
+    test_parse_error(
+        r"\frac{a}",
+        ParseErrorKind::MissingArgument,
+        None,
+        Token::EOF,
+    );
+    test_parse_error(r"x^", ParseErrorKind::MissingArgument, None, Token::EOF);
+    test_parse_error(
+        r"\text{abc",
+        ParseErrorKind::UnterminatedGroup,
+        Some(Token::RSeperator("}")),
+        Token::EOF,
+    );
+    test_parse_error(
+        r"\begin{pmatrix} a & b \end{bmatrix}",
+        ParseErrorKind::MismatchedEnvironment {
+            begin: "pmatrix",
+            end: "bmatrix",
+        },
+        None,
+        Token::RSeperator("}"),
+    );
+    test_parse_error(
+        r"a \right) + b",
+        ParseErrorKind::UnexpectedToken,
+        None,
+        Token::Right,
+    );
+}
+
+fn parse_recovering(input: &str) -> (Vec<Node<'_>>, Vec<(ParseErrorKind<'_>, Recovery)>) {
+    let (nodes, diagnostics) = Parser::new(input).parse_recovering();
+    let diagnostics = diagnostics
+        .iter()
+        .map(|diagnostic: &Diagnostic| (diagnostic.error.kind, diagnostic.recovery))
+        .collect();
+    (nodes, diagnostics)
+}
+
+#[test]
+fn test_parse_recovering() {
+    let (nodes, diagnostics) = parse_recovering(r"{a + b");
+    assert_eq!(
+        nodes,
+        vec![Node::Fenced {
+            open: Node::StrechedOp(true, "{").into(),
+            close: Node::StrechedOp(true, "}").into(),
+            content: Node::Row(vec![
+                Node::Letter("a", Variant::Italic),
+                Node::Operator("+"),
+                Node::Letter("b", Variant::Italic),
+            ])
+            .into(),
+        }]
+    );
+    assert_eq!(
+        diagnostics,
+        vec![(ParseErrorKind::UnterminatedGroup, Recovery::ClosedGroup)]
+    );
+
+    let (nodes, diagnostics) = parse_recovering(r"a \right) + b");
+    assert_eq!(
+        nodes,
+        vec![
+            Node::Letter("a", Variant::Italic),
+            Node::Operator("+"),
+            Node::Letter("b", Variant::Italic),
+        ]
+    );
+    assert_eq!(
+        diagnostics,
+        vec![(ParseErrorKind::UnexpectedToken, Recovery::Skipped)]
+    );
+
+    let (nodes, diagnostics) = parse_recovering(r"\frac{a}");
+    assert_eq!(
+        nodes,
+        vec![Node::Frac(
+            Node::Letter("a", Variant::Italic).into(),
+            Node::Undefined(Token::EOF).into(),
+            LineThickness::Medium,
+        )]
+    );
+    assert_eq!(
+        diagnostics,
+        vec![(ParseErrorKind::MissingArgument, Recovery::Substituted)]
+    );
+
+    let (nodes, diagnostics) = parse_recovering(r"\left( x \middle y");
+    assert_eq!(
+        nodes,
+        vec![Node::Fenced {
+            open: Node::StrechedOp(true, "(").into(),
+            close: Node::StrechedOp(true, "").into(),
+            content: Node::Row(vec![
+                Node::Letter("x", Variant::Italic),
+                Node::Undefined(Token::Letter("y", Variant::Italic)),
+            ])
+            .into(),
+        }]
+    );
+    assert_eq!(
+        diagnostics,
+        vec![
+            (ParseErrorKind::InvalidDelimiter, Recovery::Substituted),
+            (ParseErrorKind::UnterminatedGroup, Recovery::ClosedGroup),
+        ]
+    );
+}
+
+#[test]
+fn test_parse_never_panics() {
+    let inputs = [
+        r"\left",
+        r"\right",
+        r"\end{x}",
+        r"\begin{pmatrix} a & b",
+        r"\begin{pmatrix} a \end{bmatrix}",
+        r"\text{abc",
+        r"\sqrt[3",
+        r"x^{\frac",
+        r"\left\big",
+    ];
+    for input in inputs {
+        let (_, diagnostics) = Parser::new(input).parse_recovering();
+        assert!(!diagnostics.is_empty(), "{input}");
+        Parser::new(input).parse();
+    }
+}
+
+#[test]
+fn test_try_parse_span() {
+    let err = Parser::new(r"a + \left x").try_parse().unwrap_err();
+    assert_eq!(err.span, Span::new(10, 11));
+}
+
+#[test]
+fn test_lexer_spans() {
+    let input = r"\alpha  + 3.14\\";
+    let mut lexer = Lexer::new(input);
+    let mut spans = Vec::new();
+    loop {
+        let token = lexer.next_spanned();
+        spans.push((token.value, &input[token.span.start..token.span.end]));
+        if token.value == Token::EOF {
+            break;
+        }
+    }
+    assert_eq!(
+        spans,
+        vec![
+            (Token::Letter("α", Variant::Italic), r"\alpha"),
+            (Token::Operator("+"), "+"),
+            (Token::Number("3.14"), "3.14"),
+            (Token::NewLine, r"\\"),
+            (Token::EOF, ""),
+        ]
+    );
+}
+
+#[test]
+fn test_lexer_positions() {
+    let input = "\\alpha + α\r\n  𝔸 %\n\\frac{é}";
+    let mut lexer = Lexer::new(input);
+    let mut positions = Vec::new();
+    loop {
+        let token = lexer.next_spanned();
+        let position = lexer.position();
+        assert_eq!(position, Position::from_offset(input, token.span.start));
+        assert_eq!(
+            lexer.end_position(),
+            Position::from_offset(input, token.span.end)
+        );
+        positions.push((position.line, position.column, position.utf16_column));
+        if token.value == Token::EOF {
+            break;
+        }
+    }
+    assert_eq!(
+        positions,
+        vec![
+            (0, 0, 0),
+            (0, 7, 7),
+            (0, 9, 9),
+            (1, 2, 2),
+            (2, 0, 0),
+            (2, 5, 5),
+            (2, 6, 6),
+            (2, 8, 7),
+            (2, 9, 8),
+        ]
+    );
+    assert_eq!(
+        Position::from_offset(input, 1000),
+        Position {
+            line: 2,
+            column: 9,
+            utf16_column: 8,
+        }
+    );
+
+    // Positions are only counted when asked, skipping tokens must not change them
+    let mut lexer = Lexer::new(input);
+    for _ in 0..4 {
+        lexer.next_token();
+    }
+    assert_eq!(lexer.end_position(), Position::from_offset(input, 19));
+    lexer.next_token();
+    assert_eq!(lexer.position(), Position::from_offset(input, 22));
+}
+
+#[test]
+fn test_unicode_input() {
+    let same = |unicode: &str, commands: &str| {
+        assert_eq!(
+            Parser::new(unicode).try_parse(),
+            Parser::new(commands).try_parse()
+        );
+    };
+    same("α ≤ ∑_{i} x²", r"\alpha \leq \sum_{i} x^2");
+    same("∫₀¹ f → ∞", r"\int_0^1 f \to \infty");
+    same("x⁻¹ + aₙ₊₁ ∈ ℕ", r"x^{-1} + a_{n+1} \in ℕ");
+    same("eⁱ⁽ᵗ⁾", r"e^{i(t)}");
+    same("x²³ + x₁₂ + x¹⁰²⁴", r"x^{23} + x_{12} + x^{1024}");
+    same("x²³⁴⁵ + x₀₀₇", r"x^{2345} + x_{007}");
+    same("x²₃", r"x^2_3");
+    // Scripts without a base
+    same("²", r"^2");
+    same("² x", r"^2 x");
+    same("²³⁴⁵", r"^{2345}");
+    same("⁻¹ + ₙ", r"^{-1} + _n");
+
+    // Every run of digits is lexed as one number, the same digits sharing their text
+    let superscripts = ['⁰', '¹', '²', '³', '⁴', '⁵', '⁶', '⁷', '⁸', '⁹'];
+    for number in (0..1000u64).chain([1234567, 12345678901234567890]) {
+        for digits in [format!("{number}"), format!("{number:03}")] {
+            let input: String = digits
+                .bytes()
+                .map(|digit| superscripts[usize::from(digit - b'0')])
+                .collect();
+            let number = |input| {
+                Lexer::new(input).find_map(|token| match token {
+                    Token::Number(number) => Some(number),
+                    _ => None,
+                })
+            };
+            assert_eq!(number(&input), Some(digits.as_str()), "{input}");
+            assert!(core::ptr::eq(
+                number(&input).unwrap(),
+                number(&input).unwrap()
+            ));
+        }
+    }
+    same("⟨a, b⟩ ∋ x", r"\langle a, b \rangle \ni x");
+    same("a ⋅ b ⟂ c", r"a \cdot b \perp c");
+
+    let input = "x²";
+    let mut lexer = Lexer::new(input);
+    let tokens: Vec<_> = core::iter::from_fn(|| {
+        let token = lexer.next_spanned();
+        (token.value != Token::EOF).then_some((token.value, token.span))
+    })
+    .collect();
+    assert_eq!(
+        tokens,
+        vec![
+            (Token::Letter("x", Variant::Italic), Span::new(0, 1)),
+            (Token::Circumflex, Span::new(1, 1)),
+            (Token::Number("2"), Span::new(1, 3)),
+        ]
+    );
+}
+
+#[test]
+fn test_reverse_lookup() {
+    assert_eq!(
+        Token::commands_for("≤").collect::<Vec<_>>(),
+        vec![("leq", Token::Operator("≤")), ("le", Token::Operator("≤")),]
+    );
+    assert_eq!(
+        Token::commands_for("→").collect::<Vec<_>>(),
+        vec![
+            ("rightarrow", Token::Operator("→")),
+            ("to", Token::Operator("→")),
+            ("vec", Token::Over("→", Accent::True)),
+            ("overrightarrow", Token::Over("→", Accent::True)),
+        ]
+    );
+    assert_eq!(
+        Token::commands_for("←").next(),
+        Some(("leftarrow", Token::Operator("←")))
+    );
+    assert_eq!(Token::commands_for("x").next(), None);
+
+    assert_eq!(Token::Operator("→").to_command(), Some("rightarrow"));
+    assert_eq!(Token::Operator("≠").to_command(), Some("neq"));
+    assert_eq!(
+        Token::Letter("α", Variant::Italic).to_command(),
+        Some("alpha")
+    );
+    assert_eq!(Token::BigOp("∑").to_command(), Some("sum"));
+    assert_eq!(Token::Style(Variant::Italic).to_command(), Some("mathit"));
+    assert_eq!(Token::Style(Variant::Bold).to_command(), Some("mathbf"));
+    assert_eq!(Token::Letter("x", Variant::Italic).to_command(), None);
+    for (command, token) in Token::commands_for("≥") {
+        assert_eq!(Token::from_command(command), token);
+    }
+}
+
+#[test]
+fn test_symbol_registry() {
+    let leq = Symbol::lookup("leq").unwrap();
+    assert_eq!(leq.glyph(), Some("≤"));
+    assert_eq!(leq.class(), SymbolClass::Operator);
+    assert_eq!(leq.package, None);
+    assert_eq!(leq.name, "less-than or equal to");
+
+    let leqslant = Symbol::lookup("leqslant").unwrap();
+    assert_eq!(leqslant.package, Some("amssymb"));
+    assert_eq!(Symbol::lookup("sum").unwrap().class(), SymbolClass::BigOp);
+    assert_eq!(Symbol::lookup("hat").unwrap().class(), SymbolClass::Over);
+    assert_eq!(Symbol::lookup("frac").unwrap().glyph(), None);
+    assert_eq!(Symbol::lookup("foo"), None);
+
+    for symbol in SYMBOLS {
+        assert!(!symbol.name.is_empty(), "{} has no name", symbol.command);
+        let first = SYMBOLS
+            .iter()
+            .find(|s| s.command == symbol.command)
+            .unwrap();
+        assert_eq!(Token::from_command(symbol.command), first.token);
+    }
+    assert!(SYMBOLS
+        .iter()
+        .any(|symbol| symbol.class() == SymbolClass::Delimiter));
+}
+
+#[test]
+fn test_symbol_glyphs() {
+    assert_eq!(Token::from_command("ni"), Token::Operator("∋"));
+    assert_eq!(Token::from_command("langle"), Token::LSeperator("⟨"));
+    assert_eq!(Token::from_command("rangle"), Token::RSeperator("⟩"));
+    assert_eq!(Token::from_command("cdot"), Token::Operator("⋅"));
+    assert_eq!(Token::from_command("cdotp"), Token::Operator("·"));
+    assert_eq!(Token::from_command("perp"), Token::Operator("⟂"));
+    assert_eq!(Token::from_command("bot"), Token::Operator("⊥"));
+
+    for symbol in SYMBOLS {
+        let Some(glyph) = symbol.glyph() else {
+            continue;
+        };
+        if symbol.class() == SymbolClass::Function {
+            continue;
+        }
+        let mut chars = glyph.chars();
+        let c = chars.next().unwrap();
+        assert_eq!(chars.next(), None, "{} is not a character", symbol.command);
+        assert!(
+            !('\u{e000}'..='\u{f8ff}').contains(&c),
+            "{} is a private use character",
+            symbol.command
+        );
+        let Some(name) = unicode_names2::name(c) else {
+            assert!(c.is_ascii(), "{} is not assigned", symbol.command);
+            continue;
+        };
+        if matches!(
+            symbol.class(),
+            SymbolClass::Letter
+                | SymbolClass::Operator
+                | SymbolClass::BigOp
+                | SymbolClass::Delimiter
+        ) {
+            assert_eq!(
+                symbol.name,
+                name.to_string().to_lowercase(),
+                "name of {}",
+                symbol.command
+            );
+        }
+    }
+}
+
+#[test]
+fn test_lexer_comments() {
+    let input = "x % comment {\n + y \\% 5%\n%";
+    assert_eq!(
+        Lexer::new(input).collect::<Vec<_>>(),
+        vec![
+            Token::Letter("x", Variant::Italic),
+            Token::Operator("+"),
+            Token::Letter("y", Variant::Italic),
+            Token::Letter("%", Variant::Normal),
+            Token::Number("5"),
+        ]
+    );
+    assert_eq!(
+        Lexer::with_comments(input).collect::<Vec<_>>(),
+        vec![
+            Token::Letter("x", Variant::Italic),
+            Token::Comment(" comment {"),
+            Token::Operator("+"),
+            Token::Letter("y", Variant::Italic),
+            Token::Letter("%", Variant::Normal),
+            Token::Number("5"),
+            Token::Comment(""),
+            Token::Comment(""),
+        ]
+    );
+    assert_eq!(
+        Parser::new("\\frac{a % }\n}{b}").try_parse(),
+        Parser::new(r"\frac{a}{b}").try_parse()
+    );
+    assert_eq!(
+        Parser::new("\\newcommand{\\p}[1]{(#1 % )\n)} \\p{x % }\n}").try_parse(),
+        Parser::new(r"(x)").try_parse()
+    );
+}
+
+#[test]
+fn test_parser_spans() {
+    let input = r"\frac{a}{b_1} + \sqrt x'";
+    let (nodes, spans) = Parser::new(input).try_parse_spanned().unwrap();
+    let sources: Vec<_> = spans
+        .iter(&nodes)
+        .map(|spanned| &input[spanned.span.start..spanned.span.end])
+        .collect();
+    assert_eq!(
+        sources,
+        vec![
+            "a",
+            "b",
+            "1",
+            "b_1",
+            r"\frac{a}{b_1}",
+            "+",
+            "x",
+            "'",
+            "x'",
+            r"\sqrt x'",
+        ]
+    );
+    let sqrt = &nodes[2];
+    assert_eq!(spans.span_of(&nodes, sqrt), Some(Span::new(16, 24)));
+}
+
+#[test]
+fn test_parser_spans_cover_tree() {
+    let inputs = [
+        r"\frac{\mathrm{d}}{\mathrm{d} x}\int_{a(x)}^{b(x)}f(x,t)\mathrm{d} t = f(x,b(x))\cdot \frac{\mathrm{d}}{\mathrm{d} x} b(x)",
+        r"\left\{\sin\left(\frac{1}{n}\right)\right\}^{\infty}_{n}",
+        r"\sum^{n}_{i=0} i + \prod_{i} + \lim_{x \to 0} x + \int^a b",
+        r"\overbrace{a+b}^{n} \underbrace{c}_{m} \overbrace{d} \overset{!}{=}",
+        r"\binom{n}{k} \dbinom{n}{k} \mathbf{x} |x + 1| \left( a \middle| b \right)",
+        r"\begin{pmatrix} a & b \\ c & d \end{pmatrix} \begin{foo} x \end{foo}",
+        r"\begin{aligned} x &= 1 \\[2pt] & \\ y + 1 &= 2 \\ \end{aligned}",
+        r"\begin{array}{*{2}{@{x}c}|l} \hline 1 & \multicolumn{2}{c}{2} 3 \\ \cline{1-2} 4 \end{array}",
+        r"\sqrt[3]{x} \vec{v} \slashed{\partial} \text{if } x^{2}_1",
+        r"\sqrt{\sqrt{\hat{\frac{{a}}{b^{2}}}}} \frac{{x}_2}{(y)}",
+    ];
+    for input in inputs {
+        let (nodes, spans) = Parser::new(input).try_parse_spanned().unwrap();
+        let count: usize = nodes.iter().map(Node::node_count).sum();
+        assert_eq!(spans.as_slice().len(), count, "{input}");
+        for spanned in spans.iter(&nodes) {
+            let source = &input[spanned.span.start..spanned.span.end];
+            match spanned.value {
+                Node::Number(s) => assert_eq!(source, *s),
+                Node::Letter(s, Variant::Italic) if s.is_ascii() => assert_eq!(source, *s),
+                _ => {}
+            }
+        }
+    }
+}
+
+#[test]
+fn test_render_diagnostic() {
+    let render = |input: &str| {
+        let mut out = String::new();
+        let err = Parser::new(input).try_parse().unwrap_err();
+        err.render(input, &mut out).unwrap();
+        out
+    };
+    assert_eq!(
+        render(r"x + \mathbbb{A}"),
+        "error: unknown command `\\mathbbb`\n --> 1:5\n  |\n1 | x + \\mathbbb{A}\n  |     ^^^^^^^^\n  = help: did you mean `\\mathbb`?\n"
+    );
+    assert_eq!(
+        render("a\n+ \\left( b"),
+        "error: unterminated group\n --> 2:11\n  |\n2 | + \\left( b\n  |           ^\n  = help: expected `\\right`\n"
+    );
+
+    let suggestion = |input| Parser::new(input).try_parse().unwrap_err().suggestion();
+    assert_eq!(suggestion(r"\alpah"), Some("alpha"));
+    assert_eq!(suggestion(r"\mathbbb"), Some("mathbb"));
+    assert_eq!(suggestion(r"\qwertyuiop"), None);
+}
+
+fn test_expansion(input: &str, expanded: &str) {
+    assert_eq!(
+        Parser::new(input).try_parse(),
+        Parser::new(expanded).try_parse(),
+        "{input}"
+    );
+}
+
+#[test]
+fn test_macros() {
+    test_expansion(r"\newcommand{\R}{\mathbb{R}} x \in \R", r"x \in \mathbb{R}");
+    test_expansion(
+        r"\newcommand{\normal}[2]{\mathscr{N}\left(#1,#2\right)} \normal{\mu}{\sigma^2}",
+        r"\mathscr{N}\left(\mu,\sigma^2\right)",
+    );
+    test_expansion(r"\newcommand\f[1][x]{f(#1)} \f + \f[y]", r"f(x) + f(y)");
+    test_expansion(r"\def\p#1#2{#1^{#2}} \p a{b c}", r"a^{b c}");
+    test_expansion(
+        r"\newcommand{\norm}[1]{\left\| #1 \right\|} \newcommand{\nx}{\norm{x}} \nx_2",
+        r"\left\| x \right\|_2",
+    );
+    test_expansion(r"\newcommand{\e}{x} \renewcommand{\e}{y} \e", r"y");
+    test_expansion(r"\renewcommand{\vec}[1]{\mathbf{#1}} \vec v", r"\mathbf{v}");
+    test_expansion(r"\newcommand{\h}{\frac} \h12", r"\frac{1}{2}");
+    test_expansion(r"\newcommand{\t}[1]{\text{#1}} \t{a b}", r"\text{a b}");
+
+    assert_eq!(
+        Parser::new(r"\newcommand{\e}{x} \newcommand{\e}{y} \e").parse(),
+        vec![Node::Letter("x", Variant::Italic)]
+    );
+    let kind = |input| Parser::new(input).try_parse().unwrap_err().kind;
+    assert_eq!(
+        kind(r"\newcommand{\e}{x} \newcommand{\e}{y}"),
+        ParseErrorKind::AlreadyDefined("e")
+    );
+    assert_eq!(kind(r"\def\a{\a} \a"), ParseErrorKind::MacroDepth);
+    assert_eq!(
+        kind(r"\newcommand{x}{y}"),
+        ParseErrorKind::InvalidDefinition
+    );
+}
+
+#[test]
+fn test_macro_spans() {
+    let input = r"\newcommand{\pair}[2]{(#1, #2)} \pair{a}b";
+    let (nodes, spans) = Parser::new(input).try_parse_spanned().unwrap();
+    let sources: Vec<_> = spans
+        .iter(&nodes)
+        .map(|spanned| &input[spanned.span.start..spanned.span.end])
+        .collect();
+    assert_eq!(sources, vec![r"\pair{a}b"; 7]);
+}
+
+#[test]
+fn test_macro_table() {
+    let mut macros = MacroTable::new();
+    macros
+        .define("R", 0, r"\mathbb{R}")
+        .define(r"\norm", 1, r"\left\| #1 \right\|")
+        .define("vec", 1, r"\mathbf{#1}")
+        .define_optional("E", 2, "X", r"\mathbb{E}_{#1}[#2]");
+    assert_eq!(macros.len(), 4);
+    assert!(macros.contains(r"\norm") && macros.contains("norm"));
+    assert_eq!(
+        macros.names().collect::<Vec<_>>(),
+        vec!["E", "R", "norm", "vec"]
+    );
+
+    let parse = |input| Parser::with_macros(input, &macros).try_parse();
+    assert_eq!(
+        parse(r"\norm{\vec x} \in \R"),
+        Parser::new(r"\left\| \mathbf{x} \right\| \in \mathbb{R}").try_parse()
+    );
+    assert_eq!(
+        parse(r"\E{Y} + \E[Z]{Y}"),
+        Parser::new(r"\mathbb{E}_{X}[Y] + \mathbb{E}_{Z}[Y]").try_parse()
+    );
+    assert_eq!(
+        parse(r"\renewcommand{\R}{\mathbb{C}} \R"),
+        Parser::new(r"\mathbb{C}").try_parse()
+    );
+    assert_eq!(
+        parse(r"\newcommand{\R}{x}").unwrap_err().kind,
+        ParseErrorKind::AlreadyDefined("R")
+    );
+
+    assert!(macros.remove("vec"));
+    assert!(!macros.remove("vec"));
+    assert_eq!(
+        Parser::with_macros(r"\vec x", &macros).try_parse(),
+        Parser::new(r"\vec x").try_parse()
+    );
+}
+
+#[test]
+fn test_operators() {
+    let argmax = || {
+        Box::new(Node::Row(vec![
+            Node::Function("arg", None),
+            Node::Space(Space::from_mu(3)),
+            Node::Function("max", None),
+        ]))
+    };
+    let x = || Box::new(Node::Letter("x", Variant::Italic));
+    let n = || Box::new(Node::Letter("n", Variant::Italic));
+    assert_eq!(
+        Parser::new(r"\operatorname*{arg\,max}_x").try_parse(),
+        Ok(vec![Node::Underset {
+            under: x(),
+            target: argmax(),
+        }])
+    );
+    assert_eq!(
+        Parser::new(r"\DeclareMathOperator*{\argmax}{arg\,max} \argmax^n_x").try_parse(),
+        Ok(vec![Node::UnderOver {
+            target: argmax(),
+            under: x(),
+            over: n(),
+        }])
+    );
+    assert_eq!(
+        Parser::new(r"\DeclareMathOperator{\argmax}{arg\,max} \argmax_x").try_parse(),
+        Ok(vec![Node::Subscript(argmax(), x())])
+    );
+    assert_eq!(
+        Parser::new(r"\lim^n").try_parse(),
+        Ok(vec![Node::Overset {
+            over: n(),
+            target: Box::new(Node::Function("lim", None)),
+        }])
+    );
+    assert_eq!(
+        Parser::new(r"\DeclareMathOperator{\sin}{sin}")
+            .try_parse()
+            .unwrap_err()
+            .kind,
+        ParseErrorKind::AlreadyDefined("sin")
+    );
+
+    let mut macros = MacroTable::new();
+    macros.define_operator("tr", "tr", false);
+    macros.define_operator(r"\argmin", "arg\\,min", true);
+    assert_eq!(
+        Parser::with_macros(r"\tr A + \argmin_{x} f", &macros).try_parse(),
+        Parser::new(r"\operatorname{tr} A + \operatorname*{arg\,min}_{x} f").try_parse()
+    );
+
+    let input = r"\DeclareMathOperator*{\argmax}{argmax} \argmax_{x} f";
+    let (nodes, spans) = Parser::new(input).try_parse_spanned().unwrap();
+    let sources: Vec<_> = spans
+        .iter(&nodes)
+        .map(|spanned| &input[spanned.span.start..spanned.span.end])
+        .collect();
+    assert_eq!(sources, vec!["x", r"\argmax", r"\argmax_{x}", "f"]);
+
+    let input = r"\operatorname*{arg\,max}_{x} \operatorname{arg\;min}^2";
+    let (nodes, spans) = Parser::new(input).try_parse_spanned().unwrap();
+    let sources: Vec<_> = spans
+        .iter(&nodes)
+        .map(|spanned| &input[spanned.span.start..spanned.span.end])
+        .collect();
+    let (max, min) = (r"\operatorname*{arg\,max}", r"\operatorname{arg\;min}");
+    assert_eq!(
+        sources,
+        vec![
+            "x",
+            max,
+            max,
+            max,
+            max,
+            r"\operatorname*{arg\,max}_{x}",
+            min,
+            min,
+            min,
+            min,
+            "2",
+            r"\operatorname{arg\;min}^2",
+        ]
+    );
+}
+
+#[test]
+fn test_preamble() {
+    let preamble = Preamble::scan(include_str!("../examples/parsed.tex"));
+    assert_eq!(preamble.packages.len(), 21);
+    assert_eq!(
+        preamble.packages[0],
+        Package {
+            name: "amsmath",
+            options: vec![],
+        }
+    );
+    assert_eq!(
+        preamble.packages[18],
+        Package {
+            name: "geometry",
+            options: vec![
+                "left=1.35in",
+                "right=1.35in",
+                "bottom=1in",
+                "top=1.1in",
+                "headsep=0.2in"
+            ],
+        }
+    );
+    assert!(["b", "normal", "R", "argmax", "numberthis"]
+        .iter()
+        .all(|name| preamble.macros.contains(name)));
+
+    let parse = |input| Parser::with_macros(input, &preamble.macros).parse();
+    assert_eq!(
+        parse(r"X_1,...,X_n\iid\normal{\mu}{\sigma^2}"),
+        Parser::new(
+            r"X_1,...,X_n\stackrel{\smash{\text{iid}}}{\sim}\mathcal{N}\left(\mu,\sigma^2\right)"
+        )
+        .parse()
+    );
+    assert_eq!(
+        parse(r"\b{H} \in \R"),
+        Parser::new(r"\mathbf{H} \in \mathbb{R}").parse()
+    );
+    assert_eq!(
+        parse(r"\argmax_{x\in[0,1]} f(x)"),
+        Parser::new(r"\operatorname*{argmax}_{x\in[0,1]} f(x)").parse()
+    );
+
+    let preamble = Preamble::scan(
+        r"\documentclass{article}
+        \usepackage[utf8]{inputenc} % \usepackage{ignored}
+        \title{On \emph{Things}} \author{A. Person}
+        \begin{document} \newcommand{\late}{x} \end{document}",
+    );
+    assert_eq!(
+        preamble.packages,
+        vec![Package {
+            name: "inputenc",
+            options: vec!["utf8"],
+        }]
+    );
+    assert_eq!(preamble.title, Some(r"On \emph{Things}"));
+    assert_eq!(preamble.author, Some("A. Person"));
+    assert!(preamble.macros.is_empty());
+}
+
+#[test]
+fn test_owned_nodes() {
+    let owned: Vec<NodeBuf> = {
+        let input = String::from(r"\frac{\alpha}{2} + \text{ok} \sqrt[3]{\foo}");
+        Parser::new(&input)
+            .parse()
+            .into_iter()
+            .map(Node::into_owned)
+            .collect()
+    };
+    let borrowed = Parser::new(r"\frac{\alpha}{2} + \text{ok} \sqrt[3]{\foo}").parse();
+    assert_eq!(owned.len(), borrowed.len());
+    for (owned, borrowed) in owned.iter().zip(&borrowed) {
+        assert_eq!(owned, &borrowed.clone().map_text(String::from));
+        assert_eq!(owned.inner_str(), borrowed.inner_str());
+    }
+
+    let token = Token::Function("sin").into_owned();
+    assert_eq!(token, TokenBuf::Function(String::from("sin")));
+    assert_eq!(NodeBuf::Number("2".into()).inner_str(), Some("2"));
+}
+
+#[test]
+fn test_visitors() {
+    struct Letters<'n>(Vec<&'n str>);
+
+    impl<'n, 'a: 'n> Visit<'n, &'a str> for Letters<'n> {
+        fn visit_letter(&mut self, letter: &'n &'a str, _variant: Variant) {
+            self.0.push(letter);
+        }
+    }
+
+    struct Rename;
+
+    impl VisitMut<&str> for Rename {
+        fn visit_letter_mut(&mut self, letter: &mut &str, _variant: &mut Variant) {
+            if *letter == "x" {
+                *letter = "y";
+            }
+        }
+    }
+
+    struct Unfrac;
+
+    impl<'a> Fold<&'a str> for Unfrac {
+        fn fold_frac(
+            &mut self,
+            numerator: Box<Node<'a>>,
+            denominator: Box<Node<'a>>,
+            _thickness: LineThickness,
+        ) -> Node<'a> {
+            Node::Row(vec![
+                self.fold_node(*numerator),
+                Node::Operator("/"),
+                self.fold_node(*denominator),
+            ])
+        }
+    }
+
+    let nodes = Parser::new(r"\sqrt[n]{x} + \frac{\frac{a}{x}}{b_x}").parse();
+    let mut letters = Letters(Vec::new());
+    for node in &nodes {
+        letters.visit_node(node);
+    }
+    assert_eq!(letters.0, vec!["n", "x", "a", "x", "b", "x"]);
+
+    let mut renamed = nodes.clone();
+    for node in &mut renamed {
+        Rename.visit_node_mut(node);
+    }
+    assert_eq!(
+        renamed,
+        Parser::new(r"\sqrt[n]{y} + \frac{\frac{a}{y}}{b_y}").parse()
+    );
+
+    let unfrac: Vec<_> = nodes
+        .into_iter()
+        .map(|node| Unfrac.fold_node(node))
+        .collect();
+    assert_eq!(
+        unfrac[2],
+        Node::Row(vec![
+            Node::Row(vec![
+                Node::Letter("a", Variant::Italic),
+                Node::Operator("/"),
+                Node::Letter("x", Variant::Italic),
+            ]),
+            Node::Operator("/"),
+            Node::Subscript(
+                Box::new(Node::Letter("b", Variant::Italic)),
+                Box::new(Node::Letter("x", Variant::Italic)),
+            ),
+        ])
+    );
+}
+
+#[test]
+fn test_descendants() {
+    let node = Node::Frac(
+        Box::new(Node::Row(vec![
+            Node::Letter("a", Variant::Italic),
+            Node::Number("1"),
+        ])),
+        Box::new(Node::Letter("b", Variant::Italic)),
+        LineThickness::Medium,
+    );
+    let names: Vec<_> = node
+        .descendants()
+        .map(|node| node.inner_str().unwrap_or("-"))
+        .collect();
+    assert_eq!(names, vec!["-", "-", "a", "1", "b"]);
+    assert_eq!(node.descendants().count(), node.post_order().count());
+}
+
+#[test]
+fn test_ast() {
+    let input = r"\frac{a}{b} + \sqrt[3]{x_1^2} \left( y \right) \sum_{i=0}^n i";
+    let nodes = Parser::new(input).parse();
+    let ast = Parser::new(input).parse_ast();
+    assert_eq!(ast, Ast::from_nodes(nodes.clone()));
+    assert_eq!(ast.to_nodes(), nodes);
+    assert_eq!(ast.roots().count(), nodes.len());
+    assert_eq!(
+        ast.len(),
+        nodes
+            .iter()
+            .map(|node| node.post_order().count())
+            .sum::<usize>()
+    );
+
+    for (root, node) in ast.roots().zip(&nodes) {
+        let flat: Vec<_> = root.descendants().map(NodeRef::inner_str).collect();
+        let boxed: Vec<_> = node.descendants().map(Node::inner_str).collect();
+        assert_eq!(flat, boxed);
+        assert_eq!(root.children().count(), node.children().count());
+    }
+
+    // Node ids follow the span table
+    let (nodes, spans) = Parser::new(input).try_parse_spanned().unwrap();
+    let ast = Ast::from_nodes(nodes);
+    let frac = ast.roots().next().unwrap();
+    assert!(matches!(frac.kind(), AstNode::Frac(..)));
+    let span = spans.as_slice()[frac.id().index()];
+    assert_eq!(&input[span.start..span.end], r"\frac{a}{b}");
+    let numerator = frac.child(0).unwrap();
+    assert_eq!(numerator.inner_str(), Some("a"));
+    assert_eq!(
+        ast.get(numerator.id()).map(NodeRef::id),
+        Some(numerator.id())
+    );
+    assert_eq!(
+        ast.iter().last().map(NodeRef::id),
+        ast.roots().last().map(NodeRef::id)
+    );
+
+    for input in [
+        r"x^a_b + x_a^b + {x}_1 + {x} + x''",
+        r"\overbrace{a+b}^{n} \underbrace{c}_m \overbrace{d}",
+        r"\lim_{x \to 0} \limsup_n \operatorname*{arg\,max}_x^y \operatorname{sgn} x",
+        r"\int_0^1 \int^1_0 \sum^n_{i} \prod^n \oint x",
+        r"() (x) \{a, b\} |x| \left. x \middle| y \right] \bigl( z \bigr) \binom{n}{k} \dbinom12",
+        r"\begin{pmatrix} a & b \\ c & d \\ \end{pmatrix} \begin{cases} x \end{cases}",
+        r"\begin{array}{*{2}{@{,}c}|l} a & b & c \\ \hline \multicolumn{2}{c}{d} & e \end{array}",
+        r"\mathbf{ab} \mathrm{x+y} \mathbin{\star} \slashed{\partial} \sqrt[n]{x} \sqrt{}",
+        r"\overset{a}{b} \underset{c}{d} \hat{x} \vec x \frac{}{} \frac{{a}}{b} ( [ }",
+    ] {
+        let ast = Parser::new(input).parse_ast();
+        assert_eq!(ast, Ast::from_nodes(Parser::new(input).parse()), "{input}");
+    }
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_serde() {
+    let nodes = Parser::new(r"\frac{x}{2} \mathbf{A}_{\alpha} \sqrt[n]{\left( y \right)}").parse();
+    let json = serde_json::to_string(&nodes).unwrap();
+    assert_eq!(serde_json::from_str::<Vec<Node>>(&json).unwrap(), nodes);
+    let owned: Vec<NodeBuf> = serde_json::from_str(&json).unwrap();
+    assert_eq!(Ast::from_nodes(owned.clone()).to_nodes(), owned);
+
+    assert_eq!(
+        serde_json::to_value(&nodes[0]).unwrap(),
+        serde_json::json!({
+            "type": "Frac",
+            "value": [
+                { "type": "Letter", "value": ["x", "Italic"] },
+                { "type": "Number", "value": "2" },
+                { "type": "Medium" },
+            ],
+        })
+    );
+    assert_eq!(
+        serde_json::to_value(Token::Over("^", Accent::True)).unwrap(),
+        serde_json::json!({ "type": "Over", "value": ["^", "True"] })
+    );
+    assert_eq!(
+        serde_json::to_value(LineThickness::Length(2)).unwrap(),
+        serde_json::json!({ "type": "Length", "value": 2 })
+    );
+
+    // Escaped text can't be borrowed from the JSON, but can be read into owned nodes
+    let json = r#"{ "type": "Text", "value": ["a\"b", "Normal"] }"#;
+    assert!(serde_json::from_str::<Node>(json).is_err());
+    assert_eq!(
+        serde_json::from_str::<NodeBuf>(json).unwrap(),
+        NodeBuf::Text(r#"a"b"#.into(), Variant::Normal)
+    );
+
+    let tex = replace_latex(r"where $x^2$ holds");
+    let json = serde_json::to_string(&tex).unwrap();
+    assert_eq!(serde_json::from_str::<Vec<TexNode>>(&json).unwrap(), tex);
+
+    let ast = Parser::new(r"\frac{a}{b} c").parse_ast();
+    let json = serde_json::to_string(&ast).unwrap();
+    assert_eq!(serde_json::from_str::<Ast<&str>>(&json).unwrap(), ast);
+}
+
+#[test]
+fn test_to_latex() {
+    let inputs = [
+        r"\frac{x + 1}{y - 2}",
+        r"\left\{\sin\left(\frac{1}{n}\right)\right\}_{n}^{\infty}",
+        r"\int_{a}^bf(x)dv x",
+        r"\text{Hello World}   x",
+        r"{{{a}}} fxb",
+        r"\left(\frac{a}{b}\right) \left\{ a+b\right\} \left(\right)",
+        r"\langle x \rangle \left\langle x \right\rangle",
+        r"\alpha  + 3.14\\ x²",
+        r"\frac{a}{b_1} + \sqrt{x'} + x''",
+        r"\left\| \mathbf{x} \right\| \in \mathbb{R} \mathbf{ab} \mathbf{\alpha}",
+        r"\mathbb{E}_{X}[Y] \vec x \hat{x + y}",
+        r"\operatorname{tr} A + \operatorname*{arg\,min}_{x} f \lim^n \lim_{x\to0} x",
+        r"\DeclareMathOperator*{\argmax}{arg\,max} \argmax^n_x",
+        r"\operatorname{arg\quad min}_x + \operatorname*{\!sup}^n",
+        r"\{ a, b \} \cup \{\} \left( x \right\} \left\{ y \right.",
+        r"\sqrt[n]{x} + \frac{\frac{a}{x}}{b_x} \sqrt[3]{x_1^2}",
+        r"\left( y \right) \sum_{i=0}^n i \sum^n \prod_i \int_a^b",
+        r"\frac{\mathrm{d}}{\mathrm{d} x}\int_{a(x)}^{b(x)}f(x,t)\mathrm{d} t",
+        r"\overbrace{a+b}^{n} \underbrace{c}_{m} \overbrace{d} \underbrace{e}",
+        r"\overset{!}{=} \underset{a}{b + c}",
+        r"\binom{n}{k} \dbinom{n}{k} \tbinom{a + b}{2} \mathbf{ab}",
+        r"\left( a \middle| b \right) \big( \Big]",
+        r"\left. f \right|_0^1 \left( x \right.",
+        r"\begin{pmatrix} a & b \\ c & d \end{pmatrix} \begin{bmatrix} 1 \end{bmatrix}",
+        r"\begin{matrix} a \\ b \end{matrix} \begin{align*} x &= 1 \\ y &= 2 \end{align*}",
+        r"\begin{vmatrix} a \end{vmatrix} \begin{Vmatrix} a \end{Vmatrix} \begin{foo} x \end{foo}",
+        r"\begin{matrix} a & b \\[2pt] c \\[-0.5em] \end{matrix} \begin{pmatrix} & \\ \\ \end{pmatrix}",
+        r"\begin{array}{c|c||l@{\,}p{2.5cm}*{2}{|r}} \hline a & b \\ \cline{1-2} \multicolumn{2}{|c|}{x} y & z \\ \hline \end{array}",
+        r"\left( \begin{array}{@{}cc@{}} a & b \end{array} \right) \begin{array}{*{3}{c}} 1 & 2 & 3 \end{array}",
+        r"\slashed{\partial} \quad a \, b \; c \! d",
+        r"x_{\text{max}} \le 2^{10} \cdot \frac 12 \sqrt2",
+        r"a \mathbin{\star} b \mathrel{:=} \mathop{x}_1 \mathinner{\frac{1}{2}}",
+    ];
+    for input in inputs {
+        let nodes = Parser::new(input).try_parse().unwrap();
+        let latex = to_latex(&nodes);
+        assert_eq!(
+            Parser::new(&latex).try_parse(),
+            Ok(nodes),
+            "{input} -> {latex}"
+        );
+    }
+
+    // The inputs of the other parser tests, and arguments the parser reads without braces
+    let inputs = [
+        r"\frac{\dv}{\dv x}\int_{a(x)}^{b(x)}f(x,t)\dv t = f(x,b(x))\cdot \frac{\dv}{\dv x} b(x)",
+        r"\text{Hello World  }x",
+        r"\left(\right) \left. f \right|_0 \left( x \right.",
+        r"\frac{a}{b_1} + \sqrt x'",
+        r"\sum^{n}_{i=0} i + \prod_{i} + \lim_{x \to 0} x + \int^a b",
+        r"\overbrace{a+b}^{n} \underbrace{c}_{m} \overbrace{d} \overset{!}{=}",
+        r"\binom{n}{k} \dbinom{n}{k} \mathbf{x} |x + 1| \left( a \middle| b \right)",
+        r"\begin{aligned} x &= 1 \\[2pt] & \\ y + 1 &= 2 \\ \end{aligned}",
+        r"\begin{array}{*{2}{@{x}c}|l} \hline 1 & \multicolumn{2}{c}{2} 3 \\ \cline{1-2} 4 \end{array}",
+        r"\sqrt[3]{x} \vec{v} \slashed{\partial} \text{if } x^{2}_1",
+        r"\sqrt{\sqrt{\hat{\frac{{a}}{b^{2}}}}} \frac{{x}_2}{(y)}",
+        "α ≤ ∑_{i} x² ∫₀¹ f → ∞ x⁻¹ + aₙ₊₁ ∈ ℕ eⁱ⁽ᵗ⁾",
+        "x²³ + x₁₂ + x¹⁰²⁴ x²³⁴⁵ + x₀₀₇ x²₃ ⟨a, b⟩ ∋ x a ⋅ b ⟂ c",
+        "² x ²³⁴⁵ ⁻¹ + ₙ",
+        r"^2 _2 ^{-1} + _n",
+        "\\frac{a % }\n}{b}",
+        r"\newcommand{\pair}[2]{(#1, #2)} \pair{a}b",
+        r"\left\| \mathbf{x} \right\| \in \mathbb{R} \mathbb{E}_{X}[Y] + \mathbb{E}_{Z}[Y]",
+        r"\operatorname*{arg\,max}_x \lim^n \operatorname{arg\;min}^2",
+        r"\DeclareMathOperator{\argmax}{arg\,max} \argmax_x",
+        r"X_1,...,X_n\stackrel{\smash{\text{iid}}}{\sim}\mathcal{N}\left(\mu,\sigma^2\right)",
+        r"\operatorname*{argmax}_{x\in[0,1]} f(x)",
+        r"\frac{\alpha}{2} + \text{ok} \sqrt[3]{\foo}",
+        r"\frac{x}{2} \mathbf{A}_{\alpha} \sqrt[n]{\left( y \right)}",
+        r"{x} (x) \binom{n}{k} \{x\}^2 \left[ x \right) \{\} \mathrel{\mathop{x}}^2",
+        r"\begin{array}{c|*{2}{@{,}l}||p{2cm}} \hline a & \multicolumn{2}{|r}{b} \\ \cline{2-3} c \end{array}",
+        r"\begin{array}{c@{,}c} a & b \\ c \\ d & e \end{array}",
+        r"\sqrt\frac12 \vec\mathbf{x} \sqrt\sqrt2 \hat\text{a} \sqrt\text{a}",
+        r"\hat\big( \sqrt\alpha' \hat11 |x| |a|_2 ||",
+    ];
+    for input in inputs {
+        let nodes = Parser::new(input).parse();
+        let latex = to_latex(&nodes);
+        assert_eq!(Parser::new(&latex).parse(), nodes, "{input} -> {latex}");
+    }
+    assert_eq!(
+        to_latex(&Parser::new(r"\sqrt\frac12 \hat11").parse()),
+        r"\sqrt{\frac{1}{2}} \hat{11}"
+    );
+    // The braces of an argument are unwrapped like the ones of a fraction's
+    assert_eq!(
+        Parser::new(r"\sqrt{\frac12} \hat{11} \vec{x}").try_parse(),
+        Parser::new(r"\sqrt\frac12 \hat11 \vec x").try_parse()
+    );
+    assert_eq!(to_latex(&Parser::new(r"^2").parse()), r"^ 2");
+
+    assert_eq!(
+        Parser::new(r"\frac{a}{b}").parse()[0].to_latex(),
+        r"\frac{a}{b}"
+    );
+    assert_eq!(
+        to_latex(&Parser::new(r"\sum_{i=0}^n x^2 \le \alpha").parse()),
+        r"\sum_{i = 0}^{n} x^{2} \leq \alpha"
+    );
+    let owned = Parser::new(r"\mathbf{x}_1").parse()[0].clone().into_owned();
+    assert_eq!(owned.to_latex(), r"\mathbf{x}_{1}");
+    // Styles are written with their math mode commands
+    assert_eq!(
+        to_latex(&Parser::new(r"\textbf{y} \mathbf{x} \textit{\Gamma}").parse()),
+        r"\mathbf{y} \mathbf{x} \mathit{\Gamma}"
+    );
+
+    // Rewritten trees are written as TeX reads them
+    let normal = |input| {
+        let nodes = Parser::new(input).try_parse().unwrap();
+        normalize(Node::Row(nodes)).to_latex()
+    };
+    assert_eq!(normal(r"\sqrt{a+b}"), r"\sqrt{a + b}");
+    assert_eq!(normal(r"\sqrt{\frac{1}{2}}"), r"\sqrt{\frac{1}{2}}");
+    assert_eq!(normal(r"\sqrt{12} \hat{x+y}"), r"{\sqrt{12} \hat{x + y}}");
+    assert_eq!(normal(r"\sqrt{x} \vec{\alpha}"), r"{\sqrt x \vec \alpha}");
+    assert_eq!(normal(r"\mathbf{x}+1"), r"{\mathbf{x} + 1}");
+    assert_eq!(normal(r"\{x\} \cup \{\}"), r"{\{ x \} \cup \{ \}}");
+}
+
+#[test]
+fn test_normalize() {
+    let normal = |input| {
+        let nodes = Parser::new(input).try_parse().unwrap();
+        normalize(Node::Row(nodes))
+    };
+    let equivalent = [
+        (r"{x}", r"x"),
+        (r"{{x}} + y", r"x + y"),
+        (r"\sqrt{x} \frac12", r"\sqrt x \frac{1}{2}"),
+        (r"1 2 {3}", r"123"),
+        (r"{x}_a^b + {y}^b_a", r"x_a^b + y^b_a"),
+        (r"x'_1", r"{x}'_1"),
+        (
+            r"\underset{a}{\overset{b}{x}}",
+            r"\overset{b}{\underset{a}{x}}",
+        ),
+        (r"\binom{n}{k}", r"\binom nk"),
+        (r"a {b {c d}}", r"a b c d"),
+    ];
+    for (input, expected) in equivalent {
+        assert_eq!(normal(input), normal(expected), "{input}");
+    }
+
+    assert_eq!(normal(r"{1}{2}"), NodeBuf::Number("12".into()));
+    assert_eq!(
+        normal(r"{a}_1 2"),
+        NodeBuf::Row(vec![
+            NodeBuf::Subscript(
+                Box::new(NodeBuf::Letter("a".into(), Variant::Italic)),
+                Box::new(NodeBuf::Number("1".into())),
+            ),
+            NodeBuf::Number("2".into()),
+        ])
+    );
+    assert_eq!(
+        normal(r"\underset{a}{\overset{b}{x}}"),
+        NodeBuf::UnderOver {
+            target: Box::new(NodeBuf::Letter("x".into(), Variant::Italic)),
+            under: Box::new(NodeBuf::Letter("a".into(), Variant::Italic)),
+            over: Box::new(NodeBuf::Letter("b".into(), Variant::Italic)),
+        }
+    );
+    assert_ne!(normal(r"(x)"), normal(r"x"));
+    // Braces the scripts would bind differently without are kept
+    assert_ne!(normal(r"{x^a}_b"), normal(r"x^a_b"));
+    assert_ne!(normal(r"{x'}_1"), normal(r"x'_1"));
+    assert_ne!(normal(r"{\sum}_i"), normal(r"\sum_i"));
+    assert_eq!(normal(r"{{x^a}}_b"), normal(r"{x^a}_b"));
+    // An opening brace read as an operator is kept
+    assert_eq!(
+        normal(r"{}y"),
+        NodeBuf::Row(vec![
+            NodeBuf::Operator("{".into()),
+            NodeBuf::Letter("y".into(), Variant::Italic),
+        ])
+    );
+    assert_ne!(normal(r"1\,2"), normal(r"12"));
+    assert_ne!(normal(r"\{x\}"), normal(r"{x}"));
+    assert_eq!(normal(r"\{{x}\}"), normal(r"\{x\}"));
+    assert_eq!(
+        normal(r"\{\}"),
+        NodeBuf::Fenced {
+            open: Box::new(NodeBuf::StrechedOp(false, "{".into())),
+            close: Box::new(NodeBuf::StrechedOp(false, "}".into())),
+            content: Box::new(NodeBuf::Row(vec![])),
+        }
+    );
+}
+
+#[test]
+fn test_hash_nodes() {
+    use std::collections::{BTreeSet, HashSet};
+
+    let inputs = [
+        r"\frac12 \, x",
+        r"\frac{1}{2}\,x",
+        r"x \; y",
+        r"x \quad y",
+        r"x\ y",
+    ];
+    let nodes: HashSet<_> = inputs
+        .iter()
+        .map(|input| Parser::new(input).parse())
+        .collect();
+    assert_eq!(nodes.len(), 3);
+    let ordered: BTreeSet<_> = inputs
+        .iter()
+        .map(|input| Parser::new(input).parse())
+        .collect();
+    assert_eq!(ordered.len(), 3);
+
+    assert_eq!(
+        Lexer::new(r"\, \! \qquad").collect::<Vec<_>>(),
+        vec![
+            Token::Space(Space::from_mu(3)),
+            Token::Space(Space::from_mu(-3)),
+            Token::Space(Space::from_mu(36)),
+        ]
+    );
+    assert!(Space::from_mu(3) < Space::from_mu(4));
+    assert_eq!(Space::from_mu(9).em(), 0.5);
+    assert_eq!(Space::from_mu(36).to_string(), "2em");
+
+    let owned: HashSet<NodeBuf> = nodes.into_iter().flatten().map(Node::into_owned).collect();
+    assert!(owned.contains(&NodeBuf::Space(Space::from_mu(5))));
+}
+
+#[test]
+fn test_atom_classes() {
+    let classes = |input| Lexer::new(input).map(Token::atom_class).collect::<Vec<_>>();
+    use AtomClass::*;
+    assert_eq!(
+        classes(r"x + 1 = \sum ( a , b ) \to \forall \partial \cdots"),
+        [Ord, Bin, Ord, Rel, Op, Open, Ord, Punct, Ord, Close, Rel, Ord, Ord, Inner].map(Some)
+    );
+    assert_eq!(
+        classes(r"≤ ∪ ⟨ ⟩ \times \le \lhd \vartriangleleft"),
+        [Rel, Bin, Open, Close, Bin, Rel, Rel, Rel].map(Some)
+    );
+    assert_eq!(classes(r"\frac \quad \mathbin"), [None; 3]);
+    assert_eq!(Token::from_command("mathrel"), Token::Atom(Rel));
+
+    let nodes = Parser::new(
+        r"\sum_{i=0}^n \frac{a}{b} \left( x \right) \sqrt{x} \mathbin{x} x_1 \, \hat x",
+    )
+    .try_parse()
+    .unwrap();
+    let classes: Vec<_> = nodes.iter().map(Node::atom_class).collect();
+    assert_eq!(
+        classes,
+        [
+            Some(Op),
+            Some(Inner),
+            Some(Inner),
+            Some(Ord),
+            Some(Bin),
+            Some(Ord),
+            None,
+            Some(Ord)
+        ]
+    );
+    let nodes = Parser::new(r"{x} (x) \binom{n}{k} \{x\}^2 \left[ x \right) \{\}")
+        .try_parse()
+        .unwrap();
+    let classes: Vec<_> = nodes.iter().map(Node::atom_class).collect();
+    assert_eq!(
+        classes,
+        [Some(Ord), Some(Inner), Some(Inner), None, Some(Inner), None]
+    );
+    let edges: Vec<_> = nodes.iter().map(Node::edge_classes).collect();
+    assert_eq!(
+        edges,
+        [
+            (Ord, Ord),
+            (Inner, Inner),
+            (Inner, Inner),
+            (Open, Close),
+            (Inner, Inner),
+            (Open, Close)
+        ]
+        .map(Some)
+    );
+    assert_eq!(
+        Parser::new(r"\mathrel{\mathop{x}}^2").try_parse(),
+        Ok(vec![Node::Superscript(
+            Box::new(Node::Atom(
+                Rel,
+                Box::new(Node::Atom(Op, Box::new(Node::Letter("x", Variant::Italic))))
+            )),
+            Box::new(Node::Number("2"))
+        )])
+    );
+}
+
+#[test]
+fn test_grid() {
+    let input = r"\begin{aligned} x &= 1 \\[2pt] y & \\ \end{aligned}";
+    let (nodes, spans) = Parser::new(input).try_parse_spanned().unwrap();
+    let Node::Matrix(grid) = &nodes[0] else {
+        panic!("{nodes:?}");
+    };
+    let x = Node::Letter("x", Variant::Italic);
+    let y = Node::Letter("y", Variant::Italic);
+    let eq = Node::Row(vec![Node::Operator("="), Node::Number("1")]);
+    assert_eq!(grid.rows, [vec![x, eq], vec![y, Node::Row(vec![])]]);
+    assert_eq!(grid.columns, [ColumnAlign::Right, ColumnAlign::Left]);
+    assert_eq!(
+        grid.row_spacing,
+        [
+            Some(Dimension {
+                value: 2000,
+                unit: Unit::Pt
+            }),
+            None
+        ]
+    );
+    let sources: Vec<_> = spans
+        .iter(&nodes)
+        .map(|spanned| &input[spanned.span.start..spanned.span.end])
+        .collect();
+    assert_eq!(sources, ["x", "=", "1", "= 1", "y", "", input]);
+
+    // Children walk the rows, the cells in the order `Grid::child` numbers them
+    let input = r"\begin{array}{c@{,}c} a & b \\ c \\ d & e \end{array}";
+    let nodes = Parser::new(input).parse();
+    let Node::Matrix(grid) = &nodes[0] else {
+        panic!("{nodes:?}");
+    };
+    let indexed: Vec<_> = (0..).map_while(|index| grid.child(index)).collect();
+    assert_eq!(nodes[0].children().collect::<Vec<_>>(), indexed);
+    assert_eq!(indexed.len(), 6);
+    let ast = Ast::from_nodes(nodes.clone());
+    let root = ast.roots().next().unwrap();
+    let flat: Vec<_> = root.children().map(NodeRef::inner_str).collect();
+    let boxed: Vec<_> = indexed.iter().map(|node| node.inner_str()).collect();
+    assert_eq!(flat, boxed);
+    assert_eq!(nodes[0].post_order().count(), nodes[0].node_count());
+
+    assert_eq!(Dimension::parse("-0.5em").unwrap().to_string(), "-0.5em");
+    assert_eq!(Dimension::parse("1.25 mu").unwrap().value, 1250);
+    assert_eq!(Dimension::parse("2furlong"), None);
+    assert_eq!(Dimension::parse("2147483.999pt"), None);
+    assert_eq!(Dimension::parse("2147483.647pt").unwrap().value, i32::MAX);
+    let error = Parser::new(r"\begin{matrix} a \\[2147483.999pt] b \end{matrix}")
+        .try_parse()
+        .unwrap_err();
+    assert_eq!(
+        error.kind,
+        ParseErrorKind::InvalidDimension("2147483.999pt")
+    );
+    let error = Parser::new(r"\begin{matrix} a \\[2] b \end{matrix}")
+        .try_parse()
+        .unwrap_err();
+    assert_eq!(error.kind, ParseErrorKind::InvalidDimension("2"));
+}
+
+#[test]
+fn test_array() {
+    let input = r"\begin{array}{c|*{2}{@{,}l}||p{2cm}} \hline a & \multicolumn{2}{|r}{b} \\ \cline{2-3} c \end{array}";
+    let nodes = Parser::new(input).try_parse().unwrap();
+    let Node::Matrix(grid) = &nodes[0] else {
+        panic!("{nodes:?}");
+    };
+    let letter = |letter| Node::Letter(letter, Variant::Italic);
+    assert_eq!(
+        grid.rows,
+        [vec![letter("a"), letter("b")], vec![letter("c")]]
+    );
+    use ColumnAlign::*;
+    assert_eq!(grid.columns, [Center, Left, Left, Left]);
+    let width = Dimension {
+        value: 2000,
+        unit: Unit::Cm,
+    };
+    assert_eq!(grid.widths, [None, None, None, Some(width)]);
+    assert_eq!(grid.vlines, [1, 3, 3]);
+    assert_eq!(
+        grid.separators,
+        [(1, Node::Operator(",")), (2, Node::Operator(","))]
+    );
+    assert_eq!(
+        grid.hlines,
+        [
+            HLine {
+                row: 0,
+                columns: None
+            },
+            HLine {
+                row: 1,
+                columns: Some((1, 2))
+            }
+        ]
+    );
+    assert_eq!(
+        grid.multicolumns,
+        [MultiColumn {
+            row: 0,
+            cell: 1,
+            columns: 2,
+            align: Right,
+            width: None,
+            vlines: vec![0],
+        }]
+    );
+
+    let nodes = Parser::new(r"\begin{array}{*{4096}{c}} a \end{array}")
+        .try_parse()
+        .unwrap();
+    assert!(matches!(&nodes[0], Node::Matrix(grid) if grid.columns.len() == 4096));
+
+    for (input, text) in [
+        (r"\begin{array}{cx} a \end{array}", "x"),
+        (r"\begin{array}{c} \cline{3-1} a \end{array}", "3-1"),
+        (r"\begin{array}{*{n}{c}} a \end{array}", "n"),
+        (r"\begin{array}{*{100000000}{c}} a \end{array}", "100000000"),
+        (
+            r"\begin{array}{*{40000000000}{c}} a \end{array}",
+            "40000000000",
+        ),
+        (r"\begin{array}{*{100}{*{100}{|c}}} a \end{array}", "100"),
+        (
+            r"\begin{array}{c} \multicolumn{5000}{c}{a} \end{array}",
+            "5000",
+        ),
+        (
+            r"\begin{array}{c} \multicolumn{2}{cc}{a} \end{array}",
+            "{cc}",
+        ),
+    ] {
+        let error = Parser::new(input).try_parse().unwrap_err();
+        assert_eq!(
+            error.kind,
+            ParseErrorKind::InvalidColumnSpec(text),
+            "{input}"
+        );
+    }
+}
@@ -0,0 +1,604 @@
+//! Traits walking and rewriting [`Node`](crate::Node) trees
+//!
+//! Every trait has a method per variant whose default implementation recurses into the children
+//! of the node in the order they appear in the source, so an implementation only overrides the
+//! variants it cares about.
+
+use crate::{
+    models::{Accent, AtomClass, DisplayStyle, Grid, LineThickness, NodeOf, Space, Variant},
+    token::TokenOf,
+};
+use alloc::{boxed::Box, vec::Vec};
+
+/// Walks a tree by reference, see [`Node::descendants`](crate::Node::descendants) for a plain
+/// iterator
+pub trait Visit<'n, S: 'n> {
+    /// Visits any node, override it to act on every node before or after [`Visit::walk_node`]
+    fn visit_node(&mut self, node: &'n NodeOf<S>) {
+        self.walk_node(node)
+    }
+
+    /// Dispatches `node` to the method of its variant
+    fn walk_node(&mut self, node: &'n NodeOf<S>) {
+        match node {
+            NodeOf::Package(name) => self.visit_package(name),
+            NodeOf::Title(title) => self.visit_title(title),
+            NodeOf::Number(number) => self.visit_number(number),
+            NodeOf::Operator(op) => self.visit_operator(op),
+            NodeOf::Text(text, variant) => self.visit_text(text, *variant),
+            NodeOf::Letter(letter, variant) => self.visit_letter(letter, *variant),
+            NodeOf::Function(name, arg) => self.visit_function(name, arg.as_deref()),
+            NodeOf::Space(space) => self.visit_space(*space),
+            NodeOf::Subscript(target, sub) => self.visit_subscript(target, sub),
+            NodeOf::Superscript(target, sup) => self.visit_superscript(target, sup),
+            NodeOf::SubSup { target, sub, sup } => self.visit_sub_sup(target, sub, sup),
+            NodeOf::OverOp(op, accent, target) => self.visit_over_op(op, *accent, target),
+            NodeOf::UnderOp(op, accent, target) => self.visit_under_op(op, *accent, target),
+            NodeOf::Overset { over, target } => self.visit_overset(over, target),
+            NodeOf::Underset { under, target } => self.visit_underset(under, target),
+            NodeOf::UnderOver {
+                target,
+                under,
+                over,
+            } => self.visit_under_over(target, under, over),
+            NodeOf::Sqrt(degree, content) => self.visit_sqrt(degree.as_deref(), content),
+            NodeOf::Frac(numerator, denominator, thickness) => {
+                self.visit_frac(numerator, denominator, *thickness)
+            }
+            NodeOf::Row(nodes) => self.visit_row(nodes),
+            NodeOf::Fenced {
+                open,
+                close,
+                content,
+            } => self.visit_fenced(open, content, close),
+            NodeOf::StrechedOp(stretchy, op) => self.visit_streched_op(*stretchy, op),
+            NodeOf::OtherOperator(op) => self.visit_other_operator(op),
+            NodeOf::SizedParen { size, paren } => self.visit_sized_paren(size, paren),
+            NodeOf::Matrix(grid) => self.visit_matrix(grid),
+            NodeOf::Ampersand => self.visit_ampersand(),
+            NodeOf::NewLine => self.visit_new_line(),
+            NodeOf::Slashed(content) => self.visit_slashed(content),
+            NodeOf::Style(style, content) => self.visit_style(*style, content),
+            NodeOf::Atom(class, content) => self.visit_atom(*class, content),
+            NodeOf::Undefined(token) => self.visit_undefined(token),
+        }
+    }
+
+    fn visit_package(&mut self, _name: &'n S) {}
+
+    fn visit_title(&mut self, _title: &'n S) {}
+
+    fn visit_number(&mut self, _number: &'n S) {}
+
+    fn visit_operator(&mut self, _op: &'n S) {}
+
+    fn visit_text(&mut self, _text: &'n S, _variant: Variant) {}
+
+    fn visit_letter(&mut self, _letter: &'n S, _variant: Variant) {}
+
+    fn visit_function(&mut self, _name: &'n S, arg: Option<&'n NodeOf<S>>) {
+        if let Some(arg) = arg {
+            self.visit_node(arg);
+        }
+    }
+
+    fn visit_space(&mut self, _space: Space) {}
+
+    fn visit_subscript(&mut self, target: &'n NodeOf<S>, sub: &'n NodeOf<S>) {
+        self.visit_node(target);
+        self.visit_node(sub);
+    }
+
+    fn visit_superscript(&mut self, target: &'n NodeOf<S>, sup: &'n NodeOf<S>) {
+        self.visit_node(target);
+        self.visit_node(sup);
+    }
+
+    fn visit_sub_sup(&mut self, target: &'n NodeOf<S>, sub: &'n NodeOf<S>, sup: &'n NodeOf<S>) {
+        self.visit_node(target);
+        self.visit_node(sub);
+        self.visit_node(sup);
+    }
+
+    fn visit_over_op(&mut self, _op: &'n S, _accent: Accent, target: &'n NodeOf<S>) {
+        self.visit_node(target);
+    }
+
+    fn visit_under_op(&mut self, _op: &'n S, _accent: Accent, target: &'n NodeOf<S>) {
+        self.visit_node(target);
+    }
+
+    fn visit_overset(&mut self, over: &'n NodeOf<S>, target: &'n NodeOf<S>) {
+        self.visit_node(over);
+        self.visit_node(target);
+    }
+
+    fn visit_underset(&mut self, under: &'n NodeOf<S>, target: &'n NodeOf<S>) {
+        self.visit_node(under);
+        self.visit_node(target);
+    }
+
+    fn visit_under_over(
+        &mut self,
+        target: &'n NodeOf<S>,
+        under: &'n NodeOf<S>,
+        over: &'n NodeOf<S>,
+    ) {
+        self.visit_node(target);
+        self.visit_node(under);
+        self.visit_node(over);
+    }
+
+    fn visit_sqrt(&mut self, degree: Option<&'n NodeOf<S>>, content: &'n NodeOf<S>) {
+        if let Some(degree) = degree {
+            self.visit_node(degree);
+        }
+        self.visit_node(content);
+    }
+
+    fn visit_frac(
+        &mut self,
+        numerator: &'n NodeOf<S>,
+        denominator: &'n NodeOf<S>,
+        _thickness: LineThickness,
+    ) {
+        self.visit_node(numerator);
+        self.visit_node(denominator);
+    }
+
+    fn visit_row(&mut self, nodes: &'n [NodeOf<S>]) {
+        for node in nodes {
+            self.visit_node(node);
+        }
+    }
+
+    fn visit_fenced(&mut self, open: &'n NodeOf<S>, content: &'n NodeOf<S>, close: &'n NodeOf<S>) {
+        self.visit_node(open);
+        self.visit_node(content);
+        self.visit_node(close);
+    }
+
+    fn visit_streched_op(&mut self, _stretchy: bool, _op: &'n S) {}
+
+    fn visit_other_operator(&mut self, _op: &'n S) {}
+
+    fn visit_sized_paren(&mut self, _size: &'n S, _paren: &'n S) {}
+
+    fn visit_matrix(&mut self, grid: &'n Grid<NodeOf<S>>) {
+        for node in grid.children() {
+            self.visit_node(node);
+        }
+    }
+
+    fn visit_ampersand(&mut self) {}
+
+    fn visit_new_line(&mut self) {}
+
+    fn visit_slashed(&mut self, content: &'n NodeOf<S>) {
+        self.visit_node(content);
+    }
+
+    fn visit_style(&mut self, _style: DisplayStyle, content: &'n NodeOf<S>) {
+        self.visit_node(content);
+    }
+
+    fn visit_atom(&mut self, _class: AtomClass, content: &'n NodeOf<S>) {
+        self.visit_node(content);
+    }
+
+    fn visit_undefined(&mut self, _token: &'n TokenOf<S>) {}
+}
+
+/// Walks a tree by mutable reference, editing it in place
+pub trait VisitMut<S> {
+    /// Visits any node, override it to act on every node before or after
+    /// [`VisitMut::walk_node_mut`]
+    fn visit_node_mut(&mut self, node: &mut NodeOf<S>) {
+        self.walk_node_mut(node)
+    }
+
+    /// Dispatches `node` to the method of its variant
+    fn walk_node_mut(&mut self, node: &mut NodeOf<S>) {
+        match node {
+            NodeOf::Package(name) => self.visit_package_mut(name),
+            NodeOf::Title(title) => self.visit_title_mut(title),
+            NodeOf::Number(number) => self.visit_number_mut(number),
+            NodeOf::Operator(op) => self.visit_operator_mut(op),
+            NodeOf::Text(text, variant) => self.visit_text_mut(text, variant),
+            NodeOf::Letter(letter, variant) => self.visit_letter_mut(letter, variant),
+            NodeOf::Function(name, arg) => self.visit_function_mut(name, arg.as_deref_mut()),
+            NodeOf::Space(space) => self.visit_space_mut(space),
+            NodeOf::Subscript(target, sub) => self.visit_subscript_mut(target, sub),
+            NodeOf::Superscript(target, sup) => self.visit_superscript_mut(target, sup),
+            NodeOf::SubSup { target, sub, sup } => self.visit_sub_sup_mut(target, sub, sup),
+            NodeOf::OverOp(op, accent, target) => self.visit_over_op_mut(op, accent, target),
+            NodeOf::UnderOp(op, accent, target) => self.visit_under_op_mut(op, accent, target),
+            NodeOf::Overset { over, target } => self.visit_overset_mut(over, target),
+            NodeOf::Underset { under, target } => self.visit_underset_mut(under, target),
+            NodeOf::UnderOver {
+                target,
+                under,
+                over,
+            } => self.visit_under_over_mut(target, under, over),
+            NodeOf::Sqrt(degree, content) => self.visit_sqrt_mut(degree.as_deref_mut(), content),
+            NodeOf::Frac(numerator, denominator, thickness) => {
+                self.visit_frac_mut(numerator, denominator, thickness)
+            }
+            NodeOf::Row(nodes) => self.visit_row_mut(nodes),
+            NodeOf::Fenced {
+                open,
+                close,
+                content,
+            } => self.visit_fenced_mut(open, content, close),
+            NodeOf::StrechedOp(stretchy, op) => self.visit_streched_op_mut(stretchy, op),
+            NodeOf::OtherOperator(op) => self.visit_other_operator_mut(op),
+            NodeOf::SizedParen { size, paren } => self.visit_sized_paren_mut(size, paren),
+            NodeOf::Matrix(grid) => self.visit_matrix_mut(grid),
+            NodeOf::Ampersand => self.visit_ampersand_mut(),
+            NodeOf::NewLine => self.visit_new_line_mut(),
+            NodeOf::Slashed(content) => self.visit_slashed_mut(content),
+            NodeOf::Style(style, content) => self.visit_style_mut(style, content),
+            NodeOf::Atom(class, content) => self.visit_atom_mut(class, content),
+            NodeOf::Undefined(token) => self.visit_undefined_mut(token),
+        }
+    }
+
+    fn visit_package_mut(&mut self, _name: &mut S) {}
+
+    fn visit_title_mut(&mut self, _title: &mut S) {}
+
+    fn visit_number_mut(&mut self, _number: &mut S) {}
+
+    fn visit_operator_mut(&mut self, _op: &mut S) {}
+
+    fn visit_text_mut(&mut self, _text: &mut S, _variant: &mut Variant) {}
+
+    fn visit_letter_mut(&mut self, _letter: &mut S, _variant: &mut Variant) {}
+
+    fn visit_function_mut(&mut self, _name: &mut S, arg: Option<&mut NodeOf<S>>) {
+        if let Some(arg) = arg {
+            self.visit_node_mut(arg);
+        }
+    }
+
+    fn visit_space_mut(&mut self, _space: &mut Space) {}
+
+    fn visit_subscript_mut(&mut self, target: &mut NodeOf<S>, sub: &mut NodeOf<S>) {
+        self.visit_node_mut(target);
+        self.visit_node_mut(sub);
+    }
+
+    fn visit_superscript_mut(&mut self, target: &mut NodeOf<S>, sup: &mut NodeOf<S>) {
+        self.visit_node_mut(target);
+        self.visit_node_mut(sup);
+    }
+
+    fn visit_sub_sup_mut(
+        &mut self,
+        target: &mut NodeOf<S>,
+        sub: &mut NodeOf<S>,
+        sup: &mut NodeOf<S>,
+    ) {
+        self.visit_node_mut(target);
+        self.visit_node_mut(sub);
+        self.visit_node_mut(sup);
+    }
+
+    fn visit_over_op_mut(&mut self, _op: &mut S, _accent: &mut Accent, target: &mut NodeOf<S>) {
+        self.visit_node_mut(target);
+    }
+
+    fn visit_under_op_mut(&mut self, _op: &mut S, _accent: &mut Accent, target: &mut NodeOf<S>) {
+        self.visit_node_mut(target);
+    }
+
+    fn visit_overset_mut(&mut self, over: &mut NodeOf<S>, target: &mut NodeOf<S>) {
+        self.visit_node_mut(over);
+        self.visit_node_mut(target);
+    }
+
+    fn visit_underset_mut(&mut self, under: &mut NodeOf<S>, target: &mut NodeOf<S>) {
+        self.visit_node_mut(under);
+        self.visit_node_mut(target);
+    }
+
+    fn visit_under_over_mut(
+        &mut self,
+        target: &mut NodeOf<S>,
+        under: &mut NodeOf<S>,
+        over: &mut NodeOf<S>,
+    ) {
+        self.visit_node_mut(target);
+        self.visit_node_mut(under);
+        self.visit_node_mut(over);
+    }
+
+    fn visit_sqrt_mut(&mut self, degree: Option<&mut NodeOf<S>>, content: &mut NodeOf<S>) {
+        if let Some(degree) = degree {
+            self.visit_node_mut(degree);
+        }
+        self.visit_node_mut(content);
+    }
+
+    fn visit_frac_mut(
+        &mut self,
+        numerator: &mut NodeOf<S>,
+        denominator: &mut NodeOf<S>,
+        _thickness: &mut LineThickness,
+    ) {
+        self.visit_node_mut(numerator);
+        self.visit_node_mut(denominator);
+    }
+
+    fn visit_row_mut(&mut self, nodes: &mut Vec<NodeOf<S>>) {
+        for node in nodes {
+            self.visit_node_mut(node);
+        }
+    }
+
+    fn visit_fenced_mut(
+        &mut self,
+        open: &mut NodeOf<S>,
+        content: &mut NodeOf<S>,
+        close: &mut NodeOf<S>,
+    ) {
+        self.visit_node_mut(open);
+        self.visit_node_mut(content);
+        self.visit_node_mut(close);
+    }
+
+    fn visit_streched_op_mut(&mut self, _stretchy: &mut bool, _op: &mut S) {}
+
+    fn visit_other_operator_mut(&mut self, _op: &mut S) {}
+
+    fn visit_sized_paren_mut(&mut self, _size: &mut S, _paren: &mut S) {}
+
+    fn visit_matrix_mut(&mut self, grid: &mut Grid<NodeOf<S>>) {
+        for node in grid.children_mut() {
+            self.visit_node_mut(node);
+        }
+    }
+
+    fn visit_ampersand_mut(&mut self) {}
+
+    fn visit_new_line_mut(&mut self) {}
+
+    fn visit_slashed_mut(&mut self, content: &mut NodeOf<S>) {
+        self.visit_node_mut(content);
+    }
+
+    fn visit_style_mut(&mut self, _style: &mut DisplayStyle, content: &mut NodeOf<S>) {
+        self.visit_node_mut(content);
+    }
+
+    fn visit_atom_mut(&mut self, _class: &mut AtomClass, content: &mut NodeOf<S>) {
+        self.visit_node_mut(content);
+    }
+
+    fn visit_undefined_mut(&mut self, _token: &mut TokenOf<S>) {}
+}
+
+/// Rebuilds a tree by value, each method returns the node replacing the one it was given
+pub trait Fold<S> {
+    /// Folds any node, override it to act on every node before or after [`Fold::walk_fold`]
+    fn fold_node(&mut self, node: NodeOf<S>) -> NodeOf<S> {
+        self.walk_fold(node)
+    }
+
+    /// Dispatches `node` to the method of its variant
+    fn walk_fold(&mut self, node: NodeOf<S>) -> NodeOf<S> {
+        match node {
+            NodeOf::Package(name) => self.fold_package(name),
+            NodeOf::Title(title) => self.fold_title(title),
+            NodeOf::Number(number) => self.fold_number(number),
+            NodeOf::Operator(op) => self.fold_operator(op),
+            NodeOf::Text(text, variant) => self.fold_text(text, variant),
+            NodeOf::Letter(letter, variant) => self.fold_letter(letter, variant),
+            NodeOf::Function(name, arg) => self.fold_function(name, arg),
+            NodeOf::Space(space) => self.fold_space(space),
+            NodeOf::Subscript(target, sub) => self.fold_subscript(target, sub),
+            NodeOf::Superscript(target, sup) => self.fold_superscript(target, sup),
+            NodeOf::SubSup { target, sub, sup } => self.fold_sub_sup(target, sub, sup),
+            NodeOf::OverOp(op, accent, target) => self.fold_over_op(op, accent, target),
+            NodeOf::UnderOp(op, accent, target) => self.fold_under_op(op, accent, target),
+            NodeOf::Overset { over, target } => self.fold_overset(over, target),
+            NodeOf::Underset { under, target } => self.fold_underset(under, target),
+            NodeOf::UnderOver {
+                target,
+                under,
+                over,
+            } => self.fold_under_over(target, under, over),
+            NodeOf::Sqrt(degree, content) => self.fold_sqrt(degree, content),
+            NodeOf::Frac(numerator, denominator, thickness) => {
+                self.fold_frac(numerator, denominator, thickness)
+            }
+            NodeOf::Row(nodes) => self.fold_row(nodes),
+            NodeOf::Fenced {
+                open,
+                close,
+                content,
+            } => self.fold_fenced(open, content, close),
+            NodeOf::StrechedOp(stretchy, op) => self.fold_streched_op(stretchy, op),
+            NodeOf::OtherOperator(op) => self.fold_other_operator(op),
+            NodeOf::SizedParen { size, paren } => self.fold_sized_paren(size, paren),
+            NodeOf::Matrix(grid) => self.fold_matrix(*grid),
+            NodeOf::Ampersand => self.fold_ampersand(),
+            NodeOf::NewLine => self.fold_new_line(),
+            NodeOf::Slashed(content) => self.fold_slashed(content),
+            NodeOf::Style(style, content) => self.fold_style(style, content),
+            NodeOf::Atom(class, content) => self.fold_atom(class, content),
+            NodeOf::Undefined(token) => self.fold_undefined(token),
+        }
+    }
+
+    /// Folds a boxed child, reusing its allocation
+    fn fold_box(&mut self, mut node: Box<NodeOf<S>>) -> Box<NodeOf<S>> {
+        *node = self.fold_node(*node);
+        node
+    }
+
+    fn fold_package(&mut self, name: S) -> NodeOf<S> {
+        NodeOf::Package(name)
+    }
+
+    fn fold_title(&mut self, title: S) -> NodeOf<S> {
+        NodeOf::Title(title)
+    }
+
+    fn fold_number(&mut self, number: S) -> NodeOf<S> {
+        NodeOf::Number(number)
+    }
+
+    fn fold_operator(&mut self, op: S) -> NodeOf<S> {
+        NodeOf::Operator(op)
+    }
+
+    fn fold_text(&mut self, text: S, variant: Variant) -> NodeOf<S> {
+        NodeOf::Text(text, variant)
+    }
+
+    fn fold_letter(&mut self, letter: S, variant: Variant) -> NodeOf<S> {
+        NodeOf::Letter(letter, variant)
+    }
+
+    fn fold_function(&mut self, name: S, arg: Option<Box<NodeOf<S>>>) -> NodeOf<S> {
+        NodeOf::Function(name, arg.map(|arg| self.fold_box(arg)))
+    }
+
+    fn fold_space(&mut self, space: Space) -> NodeOf<S> {
+        NodeOf::Space(space)
+    }
+
+    fn fold_subscript(&mut self, target: Box<NodeOf<S>>, sub: Box<NodeOf<S>>) -> NodeOf<S> {
+        NodeOf::Subscript(self.fold_box(target), self.fold_box(sub))
+    }
+
+    fn fold_superscript(&mut self, target: Box<NodeOf<S>>, sup: Box<NodeOf<S>>) -> NodeOf<S> {
+        NodeOf::Superscript(self.fold_box(target), self.fold_box(sup))
+    }
+
+    fn fold_sub_sup(
+        &mut self,
+        target: Box<NodeOf<S>>,
+        sub: Box<NodeOf<S>>,
+        sup: Box<NodeOf<S>>,
+    ) -> NodeOf<S> {
+        NodeOf::SubSup {
+            target: self.fold_box(target),
+            sub: self.fold_box(sub),
+            sup: self.fold_box(sup),
+        }
+    }
+
+    fn fold_over_op(&mut self, op: S, accent: Accent, target: Box<NodeOf<S>>) -> NodeOf<S> {
+        NodeOf::OverOp(op, accent, self.fold_box(target))
+    }
+
+    fn fold_under_op(&mut self, op: S, accent: Accent, target: Box<NodeOf<S>>) -> NodeOf<S> {
+        NodeOf::UnderOp(op, accent, self.fold_box(target))
+    }
+
+    fn fold_overset(&mut self, over: Box<NodeOf<S>>, target: Box<NodeOf<S>>) -> NodeOf<S> {
+        NodeOf::Overset {
+            over: self.fold_box(over),
+            target: self.fold_box(target),
+        }
+    }
+
+    fn fold_underset(&mut self, under: Box<NodeOf<S>>, target: Box<NodeOf<S>>) -> NodeOf<S> {
+        NodeOf::Underset {
+            under: self.fold_box(under),
+            target: self.fold_box(target),
+        }
+    }
+
+    fn fold_under_over(
+        &mut self,
+        target: Box<NodeOf<S>>,
+        under: Box<NodeOf<S>>,
+        over: Box<NodeOf<S>>,
+    ) -> NodeOf<S> {
+        NodeOf::UnderOver {
+            target: self.fold_box(target),
+            under: self.fold_box(under),
+            over: self.fold_box(over),
+        }
+    }
+
+    fn fold_sqrt(&mut self, degree: Option<Box<NodeOf<S>>>, content: Box<NodeOf<S>>) -> NodeOf<S> {
+        let degree = degree.map(|degree| self.fold_box(degree));
+        NodeOf::Sqrt(degree, self.fold_box(content))
+    }
+
+    fn fold_frac(
+        &mut self,
+        numerator: Box<NodeOf<S>>,
+        denominator: Box<NodeOf<S>>,
+        thickness: LineThickness,
+    ) -> NodeOf<S> {
+        NodeOf::Frac(
+            self.fold_box(numerator),
+            self.fold_box(denominator),
+            thickness,
+        )
+    }
+
+    fn fold_row(&mut self, nodes: Vec<NodeOf<S>>) -> NodeOf<S> {
+        NodeOf::Row(nodes.into_iter().map(|node| self.fold_node(node)).collect())
+    }
+
+    fn fold_fenced(
+        &mut self,
+        open: Box<NodeOf<S>>,
+        content: Box<NodeOf<S>>,
+        close: Box<NodeOf<S>>,
+    ) -> NodeOf<S> {
+        let open = self.fold_box(open);
+        let content = self.fold_box(content);
+        NodeOf::Fenced {
+            open,
+            close: self.fold_box(close),
+            content,
+        }
+    }
+
+    fn fold_streched_op(&mut self, stretchy: bool, op: S) -> NodeOf<S> {
+        NodeOf::StrechedOp(stretchy, op)
+    }
+
+    fn fold_other_operator(&mut self, op: S) -> NodeOf<S> {
+        NodeOf::OtherOperator(op)
+    }
+
+    fn fold_sized_paren(&mut self, size: S, paren: S) -> NodeOf<S> {
+        NodeOf::SizedParen { size, paren }
+    }
+
+    fn fold_matrix(&mut self, grid: Grid<NodeOf<S>>) -> NodeOf<S> {
+        NodeOf::Matrix(Box::new(grid.map(|node| self.fold_node(node))))
+    }
+
+    fn fold_ampersand(&mut self) -> NodeOf<S> {
+        NodeOf::Ampersand
+    }
+
+    fn fold_new_line(&mut self) -> NodeOf<S> {
+        NodeOf::NewLine
+    }
+
+    fn fold_slashed(&mut self, content: Box<NodeOf<S>>) -> NodeOf<S> {
+        NodeOf::Slashed(self.fold_box(content))
+    }
+
+    fn fold_style(&mut self, style: DisplayStyle, content: Box<NodeOf<S>>) -> NodeOf<S> {
+        NodeOf::Style(style, self.fold_box(content))
+    }
+
+    fn fold_atom(&mut self, class: AtomClass, content: Box<NodeOf<S>>) -> NodeOf<S> {
+        NodeOf::Atom(class, self.fold_box(content))
+    }
+
+    fn fold_undefined(&mut self, token: TokenOf<S>) -> NodeOf<S> {
+        NodeOf::Undefined(token)
+    }
+}